@@ -1,85 +1,422 @@
-use std::io::BufRead;
-use std::io::BufReader;
 use std::io::BufWriter;
 use std::fs::File;
 use std::io::Write;
+use std::io::IsTerminal;
 
 use either::{Left, Right};
 
 use logentry::entry::*;
-use logtools::matchers::parse_matchers;
+use logtools::matchers::{Fields, LazyCaptures, PatternOptions, Scope, parse_matchers, parse_field_matchers};
+use logtools::timerange::TimeRange;
+use logtools::context::{Context, ContextIterator};
+use logtools::highlight::{Styles, use_color, write_highlighted};
+use logtools::inputs::{Encoding, Input, expand_inputs, parse_glob, source_label};
+use logtools::follow::FollowEntries;
+use logtools::errors::{ErrorPolicy, ReadErrors, Skipped, ignore_broken_pipe, is_broken_pipe};
+use logtools::json::JsonEntries;
+use logtools::output::{OutputEntry, OutputFormat, StructuredWriter, entry_fields, field_columns};
+use logtools::record::{Record, log_entries};
+use logtools::timerange::{has_offset, parse_timestamp};
 
 fn main() {
     std::process::exit(match main0() {
-        Ok(_) => 0,
+        Ok(it) => it,
+        Err(e) if is_broken_pipe(&*e) => 0,
         Err(e) => {
             eprintln!("ERROR: {:?}", e);
-            1
+            2
         }
     });
 }
 
-fn main0<'a>() -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let cli_app = cli_app();
-    let cli = cli_app.get_matches();
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Entries,
+    Count,
+    FilesWithMatches,
+    Quiet,
+}
 
-    let include_matchers = parse_matchers(&cli, "verbatim-includes", "regex-includes");
-    let exclude_matchers = parse_matchers(&cli, "verbatim-excludes", "regex-excludes");
+/// Returns the exit status, see `exit_status`.
+fn main0<'a>() -> std::result::Result<i32, Box<dyn std::error::Error>> {
+    let cli_app = cli_app();
+    // Usage errors exit with 2 like other errors, rather than with clap's 1, which means nothing matched.
+    let cli = match cli_app.get_matches_safe() {
+        Ok(it) => it,
+        Err(e) if e.use_stderr() => {
+            eprintln!("{}", e.message);
+            return Ok(2);
+        },
+        Err(e) => e.exit(),
+    };
 
+    let pattern_options = PatternOptions {
+        ignore_case: cli.is_present("ignore-case"),
+        whole_word: cli.is_present("word-regexp"),
+        scope: Scope::from_name(cli.value_of("scope").unwrap())?,
+    };
+    let mut include_matchers = parse_matchers(&cli, "verbatim-includes", "regex-includes", "include-files", pattern_options)?;
+    include_matchers.extend(parse_field_matchers(&cli, "field-includes")?);
+    let mut exclude_matchers = parse_matchers(&cli, "verbatim-excludes", "regex-excludes", "exclude-files", pattern_options)?;
+    exclude_matchers.extend(parse_field_matchers(&cli, "field-excludes")?);
+    let output_format = OutputFormat::from_name(cli.value_of("output-format").unwrap())?;
     let stdout = std::io::stdout();
-    let mut output = match cli.value_of("output-file") {
+    let color = output_format == OutputFormat::Text && use_color(
+        cli.value_of("color").unwrap(),
+        cli.value_of("output-file").is_none() && stdout.is_terminal(),
+    );
+    let writer = match cli.value_of("output-file") {
         Some(filename) => Left(BufWriter::new(File::create(filename)?)),
         None => Right(BufWriter::new(stdout.lock())),
     };
+    let mut output: Box<dyn termcolor::WriteColor> = match color {
+        true => Box::new(termcolor::Ansi::new(writer)),
+        false => Box::new(termcolor::NoColor::new(writer)),
+    };
 
-    let stdin = std::io::stdin();
-    let input = match cli.value_of("input-file") {
-        Some(filename) => Left(BufReader::new(File::open(filename)?)),
-        None => Right(stdin.lock()),
+    let include_glob = parse_glob(&cli, "include-glob")?;
+    let exclude_glob = parse_glob(&cli, "exclude-glob")?;
+    let inputs = match cli.values_of("input-files") {
+        Some(it) => expand_inputs(it, include_glob.as_ref(), exclude_glob.as_ref())?,
+        None => vec![Input::Stdin],
     };
+    let follow = cli.is_present("follow");
+    if follow && inputs.len() > 1 {
+        return Err("--follow takes a single input, use `logmerge --follow` to follow several files".into());
+    }
+    let idle_timeout = logtools::timerange::parse_timeout(&cli, "idle-timeout")?;
+    let encoding = Encoding::from_name(cli.value_of("encoding").unwrap())?;
+    if follow && !encoding.is_ascii_compatible() {
+        return Err(format!("--follow doesn't support {}", cli.value_of("encoding").unwrap()).into());
+    }
+    let mut skipped = Skipped::new(ErrorPolicy::from_name(cli.value_of("on-error").unwrap())?);
+    let truncate_last_dir = cli.is_present("truncate-last-dir");
+    let with_source = truncate_last_dir || cli.is_present("with-source");
 
-    let entry_regex_expr = {
-        let entry_pattern = cli.value_of("entry-pattern").unwrap();
-        match cli.is_present("skip-entry-source") {
-            true => format!("{}{}", SKIP_ENTRY_SOURCE_REGEX.as_str(), entry_pattern),
-            false => format!("^{}", entry_pattern),
-        }
+    let sample = inputs.iter().find_map(|it| match it {
+        Input::File(path) => Some(path.as_path()),
+        Input::Stdin => None,
+    });
+    let (entry_pattern, timestamp_pattern) = logtools::formats::patterns_from_cli(&cli, false, sample)?;
+    let entry_regex_expr = match cli.is_present("skip-entry-source") {
+        true => format!("{}{}", SKIP_ENTRY_SOURCE_REGEX.as_str(), entry_pattern),
+        false => format!("^{}", entry_pattern),
     };
     let entry_regex = regex::Regex::new(&entry_regex_expr)?;
-    let entries = LogEntryIterator::new(
-        &entry_regex,
-        None,
-        Box::new(input.lines().filter_map(|x| match x {
-            Err(e) => { eprintln!("ERROR: reading input: {}", e); None },
-            Ok(line) => Some(line),
-        }))
-    );
+    let parallel = cli.is_present("parallel");
+    let field_names = entry_regex.capture_names().flatten().collect::<Vec<_>>();
+    let expression_matcher = match cli.value_of("expression") {
+        Some(it) => Some(logtools::expression::parse(it, pattern_options, Some(&field_names))?),
+        None => None,
+    };
+    // Every value of an NDJSON entry is a field, so bare names are too.
+    let json_expression_matcher = match cli.value_of("expression") {
+        Some(it) => Some(logtools::expression::parse(it, pattern_options, None)?),
+        None => None,
+    };
+
+    let timestamp_pattern = timestamp_pattern.as_str();
+    let naive_timestamps = !has_offset(timestamp_pattern);
+    let time_range = TimeRange::from_cli(&cli, timestamp_pattern, naive_timestamps)?;
+    // NDJSON timestamps are UTC, see `logtools::json::parse_json_timestamp`, so relative bounds are too.
+    let json_time_range = TimeRange::from_cli(&cli, timestamp_pattern, false)?;
+
+    let mode = match (cli.is_present("quiet"), cli.is_present("files-with-matches"), cli.is_present("count")) {
+        (true, _, _) => Mode::Quiet,
+        (_, true, _) => Mode::FilesWithMatches,
+        (_, _, true) => Mode::Count,
+        _ => Mode::Entries,
+    };
+    let max_count = match mode {
+        Mode::Quiet | Mode::FilesWithMatches => Some(1),
+        _ => parse_count(&cli, "max-count")?,
+    };
+
+    let (before_context, after_context) = match mode {
+        Mode::Entries => {
+            let context = parse_count(&cli, "context")?.unwrap_or(0);
+            (
+                parse_count(&cli, "before-context")?.unwrap_or(context),
+                parse_count(&cli, "after-context")?.unwrap_or(context),
+            )
+        },
+        _ => (0, 0),
+    };
+
+    let json_timestamp_keys = logtools::json::timestamp_keys_from_cli(&cli, "json-timestamp-key");
+    let selected = |text: &str, json: Option<&serde_json::Value>| {
+        let json_fields;
+        let regex_fields;
+        let fields: &dyn Fields = match json {
+            Some(value) => {
+                json_fields = logtools::json::json_fields(value);
+                &json_fields
+            },
+            None => {
+                regex_fields = LazyCaptures::new(&entry_regex, text);
+                &regex_fields
+            },
+        };
+        (include_matchers.is_empty() || include_matchers.iter().any(|m| m.matches_entry(text, fields)))
+            && !exclude_matchers.iter().any(|m| m.matches_entry(text, fields))
+            && match json {
+                Some(_) => json_expression_matcher.as_ref(),
+                None => expression_matcher.as_ref(),
+            }.is_none_or(|m| m.matches_entry(text, fields))
+    };
+
+    let styles = Styles::default();
+    let write_separator = |output: &mut dyn termcolor::WriteColor| {
+        write_highlighted(output, "--", &[(0..2, &styles.separator)]).and_then(|_| output.write_all(b"\n"))
+    };
+    let skip_entry_source = cli.is_present("skip-entry-source");
+    let write_entry = |output: &mut dyn termcolor::WriteColor, text: &str| {
+        if !color {
+            return output.write_all(text.as_bytes());
+        }
+        let mut regions = Vec::new();
+        if skip_entry_source {
+            if let Some(it) = ENTRY_SOURCE_REGEX.find(text) {
+                regions.push((it.start()..it.end() - 2, &styles.source));
+            }
+        }
+        if let Some(it) = entry_regex.captures(text).and_then(|it| it.name("timestamp")) {
+            regions.push((it.start()..it.end(), &styles.timestamp));
+        }
+        include_matchers.iter()
+            .chain(expression_matcher.iter())
+            .flat_map(|m| m.spans(text))
+            .for_each(|it| regions.push((it, &styles.matched)));
+        write_highlighted(output, text, &regions)
+    };
+    let write_source = |output: &mut dyn termcolor::WriteColor, source: &str| {
+        write_highlighted(output, source, &[(0..source.len(), &styles.source)])
+    };
+    // Counts and file names are written as they are.
+    let structured = match (output_format, mode) {
+        (OutputFormat::Text, _) => None,
+        (_, Mode::Entries) => Some(StructuredWriter::new(output_format, field_columns(&entry_regex))),
+        _ => None,
+    };
+    let write_record = |output: &mut dyn termcolor::WriteColor, source: Option<&String>, text: &str, zdt: Option<&Zdt>, json: Option<&serde_json::Value>| {
+        if let Some(structured) = &structured {
+            let parsed = match (zdt, json) {
+                (None, None) => parse_timestamp(&entry_regex, text, timestamp_pattern),
+                _ => None,
+            };
+            return structured.write(output, &OutputEntry {
+                source: source.map(|it| it.as_str()),
+                zdt: zdt.or(parsed.as_ref()),
+                naive: naive_timestamps && json.is_none(),
+                fields: entry_fields(&entry_regex, text, json),
+                text,
+            });
+        }
+        if let Some(source) = source {
+            write_source(output, source)?;
+            output.write_all(b": ")?;
+        }
+        write_entry(output, text)?;
+        output.write_all(b"\n")
+    };
 
-    entries
-        .filter(|it| include_matchers.is_empty() || include_matchers.iter().any(|m| m.matches(&it.text)))
-        .filter(|it| !exclude_matchers.iter().any(|m| m.matches(&it.text)))
-        .map(|mut it| {
-            it.text.push('\n');
-            it
-        })
-        .take_while(|it| {
-            match output.write(it.text.as_bytes()) {
-                Err(e) => { eprintln!("ERROR: writing output: {}", e); false },
-                Ok(_) => true,
+    let has_context = before_context > 0 || after_context > 0;
+    let count_with_source = inputs.len() > 1 || with_source;
+    let mut matched_any = false;
+    let read_errors = ReadErrors::default();
+    for input in inputs {
+        let label = match &input {
+            Input::File(path) => source_label(path, truncate_last_dir),
+            Input::Stdin => "(standard input)".to_string(),
+        };
+        let source = match (&input, with_source || structured.is_some()) {
+            (Input::File(_), true) => Some(label.clone()),
+            _ => None,
+        };
+
+        let mut matched = 0;
+        let mut written = Ok(());
+        let mut searched = false;
+        if let (true, true, Input::File(path)) = (parallel, encoding.is_ascii_compatible(), &input) {
+            let data = match skipped.check(&input.name(), logtools::parallel::map_plain_file(path))? {
+                Some(it) => it.filter(|it| !logtools::json::is_ndjson_start(it)),
+                None => continue,
+            };
+            if let Some(data) = data {
+                let in_time_range = |text: &str| time_range.is_unbounded()
+                    || time_range.contains(parse_timestamp(&entry_regex, text, timestamp_pattern).as_ref());
+                // Stops the search with `Err(None)` once there are enough matches.
+                let result = logtools::parallel::search(
+                    &data,
+                    &entry_regex,
+                    encoding,
+                    |text| in_time_range(text) && selected(text, None),
+                    |text| {
+                        if max_count == Some(matched) {
+                            return Err(None);
+                        }
+                        matched += 1;
+                        if mode == Mode::Entries {
+                            write_record(&mut *output, source.as_ref(), text, None, None).map_err(Some)?;
+                        }
+                        match max_count == Some(matched) {
+                            true => Err(None),
+                            false => Ok(()),
+                        }
+                    },
+                );
+                if let Err(Some(e)) = result {
+                    written = Err(e);
+                }
+                searched = true;
+            }
+        }
+        if !searched {
+            let entry_timestamp_pattern = match time_range.is_unbounded() {
+                true => None,
+                false => Some(timestamp_pattern),
+            };
+            let entries: Box<dyn Iterator<Item = Record>> = match follow {
+                true => {
+                    if let Input::File(_) = &input {
+                        if skipped.check(&input.name(), input.open().map(|_| ()))?.is_none() {
+                            continue;
+                        }
+                    }
+                    Box::new(FollowEntries::new(
+                        input.follow(encoding),
+                        entry_regex.clone(),
+                        entry_timestamp_pattern.map(|it| it.to_string()),
+                        idle_timeout,
+                    ))
+                },
+                false => {
+                    let opened = input.open().and_then(|mut reader| {
+                        let json = encoding.is_ascii_compatible() && logtools::json::is_ndjson(&mut *reader)?;
+                        Ok((reader, json))
+                    });
+                    let reader = match skipped.check(&input.name(), opened)? {
+                        Some(it) => it,
+                        None => continue,
+                    };
+                    let lines = logtools::inputs::checked_lines(reader.0, encoding, input.name(), read_errors.clone());
+                    match reader.1 {
+                        true => Box::new(JsonEntries::new(
+                            lines,
+                            json_timestamp_keys.clone(),
+                            Some(timestamp_pattern.to_string()),
+                        )),
+                        false => Box::new(log_entries(
+                            &entry_regex,
+                            entry_timestamp_pattern,
+                            lines,
+                        )),
+                    }
+                },
+            };
+            let entries = entries.filter(|it| match it.json {
+                Some(_) => &json_time_range,
+                None => &time_range,
+            }.contains(it.zdt.as_ref()));
+
+            let mut first_in_input = true;
+            let context_entries = ContextIterator::new(entries, before_context, after_context, |it| selected(&it.text, it.json.as_ref()))
+                .with_max_selected(max_count);
+            for it in context_entries {
+                // Context entries are counted too, which is fine: the count modes have no context, and otherwise only
+                // whether anything matched matters.
+                if let Context::Entry(_) = it {
+                    matched += 1;
+                }
+                if mode != Mode::Entries {
+                    continue;
+                }
+                written = match it {
+                    Context::Entry(entry) => {
+                        let mut separated = Ok(());
+                        if first_in_input && has_context && matched_any && structured.is_none() {
+                            separated = write_separator(&mut *output);
+                        }
+                        separated.and_then(|_| write_record(&mut *output, source.as_ref(), &entry.text, entry.zdt.as_ref(), entry.json.as_ref()))
+                    },
+                    Context::Separator if structured.is_some() => Ok(()),
+                    Context::Separator => write_separator(&mut *output),
+                };
+                if follow {
+                    written = written.and_then(|_| output.flush());
+                }
+                if written.is_err() {
+                    break;
+                }
+                first_in_input = false;
             }
-        })
-        .for_each(|_| ());
+        }
 
-    Ok(())
+        // An input that failed halfway through is left out from there on.
+        skipped.check_read(&read_errors)?;
+        matched_any |= matched > 0;
+        let written = written.and_then(|_| match mode {
+            Mode::Count if count_with_source => {
+                write_source(&mut *output, &label)?;
+                writeln!(output, ": {}", matched)
+            },
+            Mode::Count => writeln!(output, "{}", matched),
+            Mode::FilesWithMatches if matched > 0 => {
+                write_source(&mut *output, &label)?;
+                writeln!(output)
+            },
+            _ => Ok(()),
+        });
+        if let Err(e) = written {
+            skipped.report();
+            ignore_broken_pipe(Err(e)).map_err(|e| format!("writing output: {}", e))?;
+            return Ok(exit_status(mode, matched_any, &skipped));
+        }
+        if mode == Mode::Quiet && matched_any {
+            break;
+        }
+    }
+
+    let finished = match &structured {
+        Some(structured) => structured.finish(&mut *output),
+        None => Ok(()),
+    }.and_then(|_| output.flush());
+    skipped.report();
+    ignore_broken_pipe(finished).map_err(|e| format!("writing output: {}", e))?;
+    Ok(exit_status(mode, matched_any, &skipped))
+}
+
+/// 0 when any log entry matched, 1 when none did, and 2 when inputs were left out, like grep.  Also like grep,
+/// a match with `-q` is 0 even when inputs were left out.
+fn exit_status(mode: Mode, matched_any: bool, skipped: &Skipped) -> i32 {
+    match (skipped.is_empty(), matched_any) {
+        (_, true) if mode == Mode::Quiet => 0,
+        (false, _) => 2,
+        (true, true) => 0,
+        (true, false) => 1,
+    }
+}
+
+fn parse_count(cli: &clap::ArgMatches, name: &str) -> Result<Option<usize>, String> {
+    match cli.value_of(name) {
+        None => Ok(None),
+        Some(it) => match it.parse::<usize>() {
+            Err(e) => Err(format!("--{} `{}` is not a non-negative integer: {}", name, it, e)),
+            Ok(it) => Ok(Some(it)),
+        },
+    }
 }
 
 lazy_static::lazy_static! {
     static ref SKIP_ENTRY_SOURCE_REGEX: String = "^[^:]+: ".to_string();
+    static ref ENTRY_SOURCE_REGEX: regex::Regex = regex::Regex::new(SKIP_ENTRY_SOURCE_REGEX.as_str()).unwrap();
     static ref SKIP_ENTRY_SOURCE_HELP: String = format!(
         "A shortcut that prepends the entry-pattern regex with `{}` to be able to match log entries \
                 produced by `logmerge -S` , i.e. to successfully match on the source log file indicator for each log entry.",
         SKIP_ENTRY_SOURCE_REGEX.as_str()
     );
+    static ref SINCE_HELP: String = format!("Only output log entries timestamped at or after this time. {}", logtools::timerange::TIME_BOUND_HELP);
+    static ref UNTIL_HELP: String = format!("Only output log entries timestamped at or before this time. {}", logtools::timerange::TIME_BOUND_HELP);
 }
 
 fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
@@ -87,6 +424,9 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
         .version(clap::crate_version!())
         .author(clap::crate_authors!())
         .about("Grep that knows what a log entry is")
+        .after_help("Exit status is 0 if any log entry matched, 1 if none did, and 2 on error, \
+                     including inputs that couldn't be read and were left out, see --on-error. \
+                     With --quiet, a match is 0 even then.")
         .arg(clap::Arg::with_name("output-file")
             .long("output-file")
             .short("o")
@@ -94,10 +434,92 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
             .value_name("file")
             .help("Write matching entries to that file (default is stdout)")
         )
-        .arg(clap::Arg::with_name("input-file")
+        .arg(clap::Arg::with_name("input-files")
             .required(false)
             .index(1)
-            .help("Read log entries from this file (default is stdin)")
+            .multiple(true)
+            .help("Read log entries from these files, `-` being stdin, `@list.txt` being the paths listed in that file, \
+                   and from the log files found in these directories (default is stdin)")
+        )
+        .arg(clap::Arg::with_name("encoding")
+             .long("encoding")
+             .value_name("encoding")
+             .possible_values(logtools::inputs::ENCODINGS)
+             .default_value("utf-8")
+             .help(logtools::inputs::ENCODING_HELP)
+        )
+        .arg(clap::Arg::with_name("on-error")
+             .long("on-error")
+             .value_name("policy")
+             .possible_values(logtools::errors::ERROR_POLICIES)
+             .default_value("warn")
+             .help("What to do about inputs that can't be read: leave them out quietly, leave them out with a warning, \
+                    or stop. The inputs left out are listed at the end.")
+        )
+        .arg(clap::Arg::with_name("include-glob")
+             .long("include-glob")
+             .value_name("glob")
+             .multiple(true)
+             .number_of_values(1)
+             .help("In directories, only search files that match this glob, or any of them when given several times")
+             .default_value(logtools::inputs::DEFAULT_INCLUDE_GLOB)
+        )
+        .arg(clap::Arg::with_name("exclude-glob")
+             .long("exclude-glob")
+             .takes_value(true)
+             .value_name("glob")
+             .multiple(true)
+             .number_of_values(1)
+             .help("In directories, skip files that match this glob, or any of them when given several times")
+        )
+        .arg(clap::Arg::with_name("follow")
+             .long("follow")
+             .help("Keep reading the input as it grows, like `tail -F`, surviving truncation and rotation of the file. \
+                    The last log entry is only output once the next one begins, or after --idle-timeout.")
+        )
+        .arg(clap::Arg::with_name("idle-timeout")
+             .long("idle-timeout")
+             .value_name("duration")
+             .default_value("1s")
+             .help("With --follow, consider the last log entry complete once nothing was appended for this long, e.g. `500ms`")
+        )
+        .arg(clap::Arg::with_name("parallel")
+             .long("parallel")
+             .short("P")
+             .conflicts_with_all(&["follow", "context", "before-context", "after-context"])
+             .help("Memory-map input files and search them on all cores, still outputting log entries in their original order. \
+                    Meant for multi-gigabyte files. Stdin and compressed files are searched as usual.")
+        )
+        .arg(clap::Arg::with_name("with-source")
+             .long("with-source")
+             .short("H")
+             .help("Prepend each log entry with its source log file path, the way `logmerge` does")
+        )
+        .arg(clap::Arg::with_name("truncate-last-dir")
+             .long("truncate-last-dir")
+             .short("S")
+             .help("Prepend each log entry with its source log file path truncated to last directory entry")
+        )
+        .arg(clap::Arg::with_name("ignore-case")
+             .long("ignore-case")
+             .short("i")
+             .help("Ignore case in the patterns of --verbatim-include, --regex-include, their exclude counterparts, \
+                    pattern files and --expression. A single pattern can override it with an `(?I)` prefix, see --verbatim-include.")
+        )
+        .arg(clap::Arg::with_name("word-regexp")
+             .long("word-regexp")
+             .short("w")
+             .help("Only match the patterns as whole words, see --ignore-case. \
+                    A single pattern can override it with a `(?W)` prefix.")
+        )
+        .arg(clap::Arg::with_name("scope")
+             .long("scope")
+             .value_name("scope")
+             .possible_values(logtools::matchers::SCOPES)
+             .default_value("all")
+             .help("Match the patterns against the first line of a log entry (`header`), the lines after it (`body`), \
+                    e.g. a stack trace, or the whole entry, see --ignore-case. \
+                    A single pattern can override it with an `(?h)`, `(?b)` or `(?a)` prefix.")
         )
         .arg(clap::Arg::with_name("verbatim-includes")
              .long("verbatim-include")
@@ -106,7 +528,11 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
              .multiple(true)
              .number_of_values(1)
              .value_name("string")
-             .help("Output log entries that match this string as is")
+             .help("Output log entries that match this string as is. A prefix of flags in `(?...)` changes how this \
+                    pattern alone is matched: `i` ignores case, `I` doesn't, `w` matches a whole word, `W` doesn't, \
+                    `h`, `b` and `a` match in the header, body or all of the entry, see --scope, e.g. `(?hiw)error`. \
+                    The case and word flags may also go before a colon, e.g. `i:timeout`. A leading `:` takes the rest \
+                    as is, e.g. `:i:timeout` matches `i:timeout` and `::` a colon. Same for all the other patterns.")
         )
         .arg(clap::Arg::with_name("regex-includes")
              .long("regex-include")
@@ -135,12 +561,160 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
              .value_name("regex")
              .help("Don't output log entries that match this regex")
         )
+        .arg(clap::Arg::with_name("include-files")
+             .long("include-file")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("file")
+             .help("Output log entries that match any pattern in this file, one per line. \
+                    Lines enclosed in slashes, like `/timeout \\d+ms/`, are regexes, the rest are matched as is. \
+                    Empty lines and lines starting with `#` are skipped.")
+        )
+        .arg(clap::Arg::with_name("exclude-files")
+             .long("exclude-file")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("file")
+             .help("Don't output log entries that match any pattern in this file, see --include-file")
+        )
+        .arg(clap::Arg::with_name("field-includes")
+             .long("field-include")
+             .short("k")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("condition")
+             .help("Output log entries whose named field, i.e. a named capture group of the entry pattern, \
+                    satisfies this condition: `name=value`, `name!=value`, `name=~substring`, `name!~substring`, \
+                    or `name=~/regex/`, e.g. `level=ERROR` or `thread=~/pool-\\d+/`")
+        )
+        .arg(clap::Arg::with_name("field-excludes")
+             .long("field-exclude")
+             .short("K")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("condition")
+             .help("Don't output log entries whose named field satisfies this condition, see --field-include")
+        )
+        .arg(clap::Arg::with_name("expression")
+             .long("expression")
+             .short("e")
+             .takes_value(true)
+             .value_name("expression")
+             .help("Output log entries that match this boolean expression, e.g. \
+                    `(ERROR or WARN) and \"OrderService\" and not /timeout \\d+ms/`. \
+                    Bare words and quoted strings are matched as is, `/.../` is a regex, \
+                    `name=value` and `name=~/regex/` test a named group of the entry pattern (see --field-include) \
+                    or a field of NDJSON input, `.name=value` any other field, as does `name == value`, \
+                    operators are `and`, `or`, `not` and parentheses. \
+                    Combined with the other filters using AND.")
+        )
+        .arg(clap::Arg::with_name("after-context")
+             .long("after-context")
+             .short("A")
+             .takes_value(true)
+             .value_name("entries")
+             .help("Also output this many log entries after each matching entry")
+        )
+        .arg(clap::Arg::with_name("before-context")
+             .long("before-context")
+             .short("B")
+             .takes_value(true)
+             .value_name("entries")
+             .help("Also output this many log entries before each matching entry")
+        )
+        .arg(clap::Arg::with_name("context")
+             .long("context")
+             .short("C")
+             .takes_value(true)
+             .value_name("entries")
+             .help("Also output this many log entries before and after each matching entry. \
+                    Groups of entries that aren't adjacent in the input are separated with `--`.")
+        )
+        .arg(clap::Arg::with_name("output-format")
+             .long("output-format")
+             .value_name("format")
+             .possible_values(logtools::output::OUTPUT_FORMATS)
+             .default_value("text")
+             .help("Output the matching log entries as they are, or as JSON (one array), NDJSON, CSV or logfmt records \
+                    of the source, the timestamp in ISO 8601, the named groups of the entry pattern, and the full text. \
+                    Context separators and colors are left out, and -c, -l and -q output stays as it is.")
+        )
+        .arg(clap::Arg::with_name("count")
+             .long("count")
+             .short("c")
+             .conflicts_with("follow")
+             .help("Only output the number of matching log entries, for each input when there are several, \
+                    or with --with-source")
+        )
+        .arg(clap::Arg::with_name("max-count")
+             .long("max-count")
+             .short("m")
+             .takes_value(true)
+             .value_name("entries")
+             .help("Stop reading an input after this many matching log entries")
+        )
+        .arg(clap::Arg::with_name("files-with-matches")
+             .long("files-with-matches")
+             .short("l")
+             .help("Only output the inputs that have a matching log entry, stopping at the first match in each")
+        )
+        .arg(clap::Arg::with_name("quiet")
+             .long("quiet")
+             .short("q")
+             .help("Don't output anything, stop at the first match. Useful for the exit status alone.")
+        )
+        .arg(clap::Arg::with_name("format")
+             .long("format")
+             .value_name("name")
+             .help(logtools::formats::FORMAT_HELP)
+        )
         .arg(clap::Arg::with_name("entry-pattern")
              .long("entry-pattern")
              .takes_value(true)
              .value_name("regex")
-             .help("Regex matching the first line of a log entry. Automatically prepended with `^`.")
-             .default_value(r"\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2},\d{3} ")
+             .help("Regex matching the first line of a log entry. Automatically prepended with `^`. \
+                    Named capture groups become the entry's fields. \
+                    --since and --until need a named capture group 'timestamp'.")
+             .default_value(r"(?P<timestamp>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2},\d{3}) ")
+        )
+        .arg(clap::Arg::with_name("timestamp-pattern")
+             .long("timestamp-pattern")
+             .value_name("pattern")
+             .help("Pattern for parsing contents of the 'timestamp' regex capture group into a date/time structure. For syntax see Rust's chrono::format::strftime docs.")
+             .default_value(r"%Y-%m-%d %H:%M:%S,%3f")
+        )
+        .arg(clap::Arg::with_name("json-timestamp-key")
+             .long("json-timestamp-key")
+             .value_name("path")
+             .help("For NDJSON input, i.e. one JSON object per line, take the timestamp from this key, \
+                    nested keys separated with dots, e.g. `meta.time` (default is the first of `timestamp`, \
+                    `@timestamp`, `time`, `ts`). NDJSON input is recognized by its first character being `{`, \
+                    and the values of the objects are the fields of the entries.")
+        )
+        .arg(clap::Arg::with_name("since")
+             .long("since")
+             .takes_value(true)
+             .value_name("time")
+             .help(&SINCE_HELP)
+        )
+        .arg(clap::Arg::with_name("until")
+             .long("until")
+             .takes_value(true)
+             .value_name("time")
+             .help(&UNTIL_HELP)
+        )
+        .arg(clap::Arg::with_name("color")
+            .long("color")
+            .takes_value(true)
+            .value_name("when")
+            .possible_values(&["auto", "always", "never"])
+            .default_value("auto")
+            .help("Highlight matches, timestamps and, with --skip-entry-source, entry sources. \
+                   `auto` highlights only when writing to a terminal and NO_COLOR isn't set.")
         )
         .arg(clap::Arg::with_name("skip-entry-source")
             .long("skip-entry-source")
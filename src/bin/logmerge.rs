@@ -1,16 +1,24 @@
-use std::io::BufReader;
 use std::io::BufWriter;
 use std::fs::File;
 use std::io::Write;
 
 use either::{Left, Right};
 
-use logentry::entry::*;
-use logentry::multi::*;
+use logtools::json::JsonEntries;
+use logtools::merge::{MergeIterator, ReorderIterator};
+use logtools::output::{OutputEntry, OutputFormat, StructuredWriter, entry_fields, field_columns};
+use logtools::record::{Record, log_entries};
+use logtools::timerange::{TimeRange, has_offset};
+use logtools::follow::{FollowEntries, FollowMerge};
+use logtools::errors::{ErrorPolicy, ReadErrors, Skipped, ignore_broken_pipe, is_broken_pipe};
+use logtools::clock::{ClockCorrection, ClockRules};
+use logtools::formats::{format_rules_from_cli, rule_format};
+use logtools::inputs::{Input, LabelTemplate, expand_inputs, group_rotated, pad_labels, parse_glob, source_label};
 
 fn main() {
-    std::process::exit(match main0() {
-        Ok(_) => 0,
+    std::process::exit(match main0(std::env::args_os().collect()) {
+        Ok(it) => it,
+        Err(e) if is_broken_pipe(&*e) => 0,
         Err(e) => {
             eprintln!("ERROR: {:?}", e);
             1
@@ -30,109 +38,289 @@ where
     eprintln!("% {}", op());
 }
 
-fn main0<'a>() -> std::result::Result<(), Box<dyn std::error::Error>> {
+fn main0<'a>(args: Vec<std::ffi::OsString>) -> std::result::Result<i32, Box<dyn std::error::Error>> {
     let cli_app = cli_app();
-    let cli = cli_app.get_matches();
+    let cli = cli_app.get_matches_from(args);
 
     DEBUG_ENABLED.store(cli.is_present("print-debug"), std::sync::atomic::Ordering::Relaxed);
 
     let stdout = std::io::stdout();
     let mut output = match cli.value_of("output-file") {
-        Some(filename) => Left(BufWriter::new(match File::create(filename) {
-            Err(e) => fatal(format!("ERROR: creating file {}: {}", filename, e)),
-            Ok(it) => it,
-        })),
+        Some(filename) => Left(BufWriter::new(
+            File::create(filename).map_err(|e| format!("creating file {}: {}", filename, e))?
+        )),
         None => Right(BufWriter::new(stdout.lock())),
     };
 
-    let entry_regex = regex::Regex::new(cli.value_of("entry-pattern").unwrap())?;
+    let include_glob = parse_glob(&cli, "include-glob")?;
+    let exclude_glob = parse_glob(&cli, "exclude-glob")?;
+    let inputs = expand_inputs(cli.values_of("inputs").unwrap(), include_glob.as_ref(), exclude_glob.as_ref())?;
+    if inputs.iter().filter(|it| matches!(it, Input::Stdin)).count() > 1 {
+        return Err("standard input can only be merged once".into());
+    }
+    // The inputs that are a source each, and the files read one after the other for each source.
+    let (inputs, parts): (Vec<_>, Vec<_>) = match cli.is_present("group-rotated") {
+        true => group_rotated(inputs).into_iter().unzip(),
+        false => inputs.into_iter().map(|it| (it.clone(), vec![it])).unzip(),
+    };
+
+    let encoding = logtools::inputs::Encoding::from_name(cli.value_of("encoding").unwrap())?;
+    let follow = cli.is_present("follow");
 
-    let include_glob = match cli.value_of("include-glob") {
-        Some(it) => Some(globset::Glob::new(it)?.compile_matcher()),
-        None => None
+    // Checks every file up front, leaving out the ones that can't be read.  The first readable file of each source
+    // is opened, to tell whether it's NDJSON, and the others only once the merge gets to them.  Followed files are
+    // only checked here, and opened again once they're followed.
+    let mut skipped = Skipped::new(ErrorPolicy::from_name(cli.value_of("on-error").unwrap())?);
+    let mut opened = Vec::new();
+    let (inputs, parts) = match follow {
+        true => {
+            let mut kept = (Vec::new(), Vec::new());
+            for (input, parts) in inputs.into_iter().zip(parts) {
+                let readable = match &input {
+                    Input::Stdin => Some(()),
+                    Input::File(_) => skipped.check(&input.name(), input.open().map(|_| ()))?,
+                };
+                if readable.is_some() {
+                    kept.0.push(input);
+                    kept.1.push(parts);
+                }
+            }
+            kept
+        },
+        false => {
+            let mut kept = (Vec::new(), Vec::new());
+            for (input, parts) in inputs.into_iter().zip(parts) {
+                let mut first = None;
+                let mut readable = Vec::new();
+                for part in parts {
+                    let checked = match first {
+                        None => part.open().and_then(|mut reader| {
+                            let json = encoding.is_ascii_compatible() && logtools::json::is_ndjson(&mut *reader)?;
+                            Ok(Some((reader, json)))
+                        }),
+                        Some(_) => part.check().map(|_| None),
+                    };
+                    if let Some(it) = skipped.check(&part.name(), checked)? {
+                        first = first.or(it);
+                        readable.push(part);
+                    }
+                }
+                if let Some(first) = first {
+                    opened.push((first, readable.clone()));
+                    kept.0.push(input);
+                    kept.1.push(readable);
+                }
+            }
+            kept
+        },
     };
-    let exclude_glob = match cli.value_of("exclude-glob") {
-        Some(it) => Some(globset::Glob::new(it)?.compile_matcher()),
-        None => None
+    // Whether each source is NDJSON, which is never followed.
+    let json_sources = match follow {
+        true => vec![false; inputs.len()],
+        false => opened.iter().map(|it| (it.0).1).collect::<Vec<_>>(),
     };
 
-    let timestamp_pattern = Some(cli.value_of("timestamp-pattern").unwrap());
     let truncate_last_dir = cli.is_present("truncate-last-dir");
     let no_source = cli.is_present("no-entry-source");
+    let template = match cli.value_of("source-label") {
+        Some(it) => Some(LabelTemplate::new(it, cli.value_of("source-label-regex"))?),
+        None => None,
+    };
+    let mut labels = inputs.iter()
+        .map(|it| match (it, &template) {
+            (Input::File(path), Some(template)) => template.render(path),
+            (Input::File(path), None) => source_label(path, truncate_last_dir),
+            (Input::Stdin, _) => "(standard input)".to_string(),
+        })
+        .collect::<Vec<_>>();
+    if let Some(width) = cli.value_of("source-label-width") {
+        pad_labels(&mut labels, width)?;
+    }
+    labels.iter().for_each(|it| debug(|| format!("Merging {}", it)));
+    let sources = labels.iter()
+        .map(|it| match no_source {
+            false => Some(it.clone()),
+            true => None,
+        })
+        .collect::<Vec<_>>();
 
-    let entry_iterators = walkdir::WalkDir::new(cli.value_of("directory").unwrap())
-        .min_depth(1)
-        .into_iter()
-        .filter_map(|it| match it {
-            Err(e) => { eprintln!("ERROR: {}", e); None },
-            Ok(dirent) => Some(dirent),
+    let format_rules = format_rules_from_cli(&cli, "source-format")?;
+    let sample = inputs.iter()
+        .zip(&parts)
+        .filter(|(input, _)| match input {
+            Input::File(path) => !format_rules.iter().any(|rule| rule.matches(path)),
+            Input::Stdin => false,
         })
-        .filter(|dirent| {
-            if !dirent.file_type().is_file() {
-                return false;
-            }
-            let filename = dirent.path();
-            debug(|| format!("Going over {}", &filename.to_string_lossy()));
-            if include_glob.as_ref().map_or(false, |it| !it.is_match(filename)) {
-                debug(|| format!("Include glob didn't match, skipping file"));
-                return false;
+        .find_map(|(_, parts)| match parts.last() {
+            Some(Input::File(path)) => Some(path.as_path()),
+            _ => None,
+        });
+    let (entry_pattern, timestamp_pattern) = logtools::formats::patterns_from_cli(&cli, true, sample)?;
+
+    // The entry regex and timestamp pattern of each input.
+    let mut parsers = Vec::new();
+    for (input, parts) in inputs.iter().zip(&parts) {
+        let format = match (input, parts.last()) {
+            (Input::File(path), Some(Input::File(sample))) => rule_format(&format_rules, path, sample, encoding)?,
+            _ => None,
+        };
+        parsers.push(match format {
+            Some(it) => (regex::Regex::new(&format!("^{}", it.entry_pattern))?, it.timestamp_pattern),
+            None => (regex::Regex::new(&entry_pattern)?, timestamp_pattern.clone()),
+        });
+    }
+
+    let clock_rules = ClockRules::from_cli(&cli, "source-timezone", "source-skew")?;
+    let clocks = inputs.iter()
+        .zip(&parsers)
+        .zip(&json_sources)
+        .map(|((input, (_, timestamp_pattern)), json)| match input {
+            // Timestamps with a UTC offset, and the RFC 3339 or epoch ones of NDJSON, are already UTC, so only
+            // the skew applies.
+            Input::File(path) if *json || has_offset(timestamp_pattern) => ClockCorrection { timezone: None, ..clock_rules.correction(path) },
+            Input::File(path) => clock_rules.correction(path),
+            Input::Stdin => ClockCorrection::default(),
+        })
+        .collect::<Vec<_>>();
+    // Relative time bounds are in UTC, except for sources with naive timestamps, i.e. local times taken as UTC.
+    let naive = clocks.iter()
+        .zip(&parsers)
+        .zip(&json_sources)
+        .map(|((clock, (_, pattern)), json)| !json && clock.timezone.is_none() && !has_offset(pattern))
+        .collect::<Vec<_>>();
+    let naive_time_range = TimeRange::from_cli(&cli, &timestamp_pattern, true)?;
+    let utc_time_range = TimeRange::from_cli(&cli, &timestamp_pattern, false)?;
+    let time_range = |index: usize| match naive[index] {
+        true => &naive_time_range,
+        false => &utc_time_range,
+    };
+
+    let output_format = OutputFormat::from_name(cli.value_of("output-format").unwrap())?;
+    let structured = match output_format {
+        OutputFormat::Text => None,
+        _ => {
+            let mut columns = Vec::new();
+            for column in parsers.iter().flat_map(|it| field_columns(&it.0)) {
+                if !columns.contains(&column) {
+                    columns.push(column);
+                }
             }
-            if exclude_glob.as_ref().map_or(false, |it| it.is_match(filename)) {
-                debug(|| format!("Exclude glob matched, skipping file"));
-                return false;
+            Some(StructuredWriter::new(output_format, columns))
+        },
+    };
+    let write_entry = |output: &mut dyn Write, entry: &Record, index: usize| match &structured {
+        Some(structured) => structured.write(output, &OutputEntry {
+            source: sources[index].as_deref().map(|it| it.trim_end()),
+            zdt: entry.zdt.as_ref(),
+            naive: entry.json.is_none() && clocks[index].timezone.is_none() && !has_offset(&parsers[index].1),
+            fields: entry_fields(&parsers[index].0, &entry.text, entry.json.as_ref()),
+            text: &entry.text,
+        }),
+        None => match &sources[index] {
+            None => writeln!(output, "{}", entry.text),
+            Some(it) => writeln!(output, "{}: {}", it, entry.text),
+        },
+    };
+
+    if follow {
+        if !encoding.is_ascii_compatible() {
+            return Err(format!("--follow doesn't support {}", cli.value_of("encoding").unwrap()).into());
+        }
+        let idle_timeout = logtools::timerange::parse_timeout(&cli, "idle-timeout")?;
+        let followed = inputs.iter()
+            .zip(&parsers)
+            .zip(&clocks)
+            .map(|((input, (entry_regex, timestamp_pattern)), clock)| FollowEntries::new(
+                input.follow(encoding),
+                entry_regex.clone(),
+                Some(timestamp_pattern.clone()),
+                idle_timeout,
+            ).with_clock(*clock))
+            .collect();
+        let written = FollowMerge::new(followed)
+            .filter(|(entry, index)| time_range(*index).contains(entry.zdt.as_ref()))
+            .try_for_each(|(entry, index)| {
+                write_entry(&mut output, &entry, index)?;
+                output.flush()
+            })
+            .and_then(|_| match &structured {
+                Some(structured) => structured.finish(&mut output).and_then(|_| output.flush()),
+                None => Ok(()),
+            });
+        skipped.report();
+        ignore_broken_pipe(written)?;
+        return Ok(exit_status(&skipped));
+    }
+
+    let json_timestamp_keys = logtools::json::timestamp_keys_from_cli(&cli, "json-timestamp-key");
+    let reorder_window = match cli.value_of("reorder-window").map_or(Ok(chrono::Duration::zero()), logtools::timerange::parse_duration)? {
+        it if it < chrono::Duration::zero() => return Err("--reorder-window must not be negative".into()),
+        it => it,
+    };
+    // Files that fail halfway through end their source early, and are left out per --on-error once merged.
+    let read_errors = ReadErrors::default();
+    let entry_iterators = opened.into_iter()
+        .zip(&parsers)
+        .zip(&clocks)
+        .zip(&labels)
+        .map(|(((((first, json), parts), (entry_regex, timestamp_pattern)), clock), label)| {
+            let lines = logtools::inputs::chained_lines(first, parts, encoding, read_errors.clone());
+            let entries: Box<dyn Iterator<Item = Record> + '_> = match json {
+                true => Box::new(JsonEntries::new(
+                    lines,
+                    json_timestamp_keys.clone(),
+                    Some(timestamp_pattern.clone()),
+                )),
+                false => Box::new(log_entries(
+                    entry_regex,
+                    Some(timestamp_pattern),
+                    lines,
+                )),
+            };
+            let entries: Box<dyn Iterator<Item = Record> + '_> = Box::new(entries.map(move |mut it| {
+                it.zdt = it.zdt.map(|zdt| clock.apply(zdt));
+                it
+            }));
+            if reorder_window.is_zero() {
+                return entries;
             }
-            debug(|| format!("File matched"));
-            true
+            let late = move |record: &Record| eprintln!(
+                "WARNING: {}: entry at {} is more than --reorder-window out of order, output late",
+                label,
+                record.zdt.map_or("".to_string(), |it| it.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)),
+            );
+            Box::new(ReorderIterator::new(entries, reorder_window, Box::new(late))) as Box<dyn Iterator<Item = Record> + '_>
         })
-        .map(|dirent| {
-            let path = dirent.path();
-            let source = match truncate_last_dir {
-                false => match no_source {
-                    false => Some(path.to_str().unwrap().to_string()),
-                    true => None,
-                },
-                true => {
-                    Some(format!("{}{}{}",
-                        match path.parent().unwrap().file_name() {
-                            Some(it) => it.to_str().unwrap(),
-                            None => ".",
-                        },
-                        std::path::MAIN_SEPARATOR,
-                        path.file_name().unwrap().to_str().unwrap()
-                    ))
-                },
-            };
-            let file = match std::fs::File::open(path) {
-                Err(e) => fatal(format!("ERROR: opening file {}: {}", path.to_string_lossy(), e)),
-                Ok(it) => it,
-            };
-            let reader = BufReader::new(file);
-            use bstr::io::BufReadExt;
-            LogEntryIteratorWithSource::new(
-                LogEntryIterator::new(
-                    &entry_regex,
-                    timestamp_pattern,
-                    Box::new(reader.byte_lines().filter_map(|it| match it {
-                        Err(e) => { eprintln!("ERROR: reading input: {}", e); None },
-                        Ok(line) => Some(String::from_utf8_lossy(line.as_ref()).to_string()),
-                    }))
-                ),
-                source
-            )
+        .collect();
+
+    // A closed output, e.g. by `| head`, only ends the merge early, the inputs left out are still reported.
+    let written = MergeIterator::new(entry_iterators)
+        .filter(|(entry, index)| time_range(*index).contains(entry.zdt.as_ref()))
+        .try_for_each(|(entry, index)| write_entry(&mut output, &entry, index))
+        .and_then(|_| match &structured {
+            Some(structured) => structured.finish(&mut output),
+            None => Ok(()),
         })
-        .collect::<Vec<LogEntryIteratorWithSource>>()
-    ;
-
-    MultiLogEntryIterator::new(entry_iterators)
-        .for_each(|(entry, source)| {
-            let text = match source {
-                None => format!("{}\n", entry.text),
-                Some(it) => format!("{}: {}\n", it, entry.text),
-            };
-            output.write(text.as_bytes()).expect("writing to output");
-        });
+        .and_then(|_| output.flush());
 
-    Ok(())
+    skipped.check_read(&read_errors)?;
+    skipped.report();
+    ignore_broken_pipe(written)?;
+
+    Ok(exit_status(&skipped))
+}
+
+/// 0 when all inputs were merged, 2 when some were left out, see --on-error.  Other errors exit with 1.
+fn exit_status(skipped: &Skipped) -> i32 {
+    match skipped.is_empty() {
+        true => 0,
+        false => 2,
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref SINCE_HELP: String = format!("Only output log entries timestamped at or after this time. {}", logtools::timerange::TIME_BOUND_HELP);
+    static ref UNTIL_HELP: String = format!("Only output log entries timestamped at or before this time. {}", logtools::timerange::TIME_BOUND_HELP);
 }
 
 fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
@@ -140,10 +328,14 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
         .version(clap::crate_version!())
         .author(clap::crate_authors!())
         .about("Merge multiple log files into one keeping chronological order of the entries.")
-        .arg(clap::Arg::with_name("directory")
+        .after_help("Exit status is 0 if all inputs were merged, 2 if some couldn't be read and were left out, \
+                     see --on-error, and 1 on other errors, including a file that couldn't be read with --on-error fail.")
+        .arg(clap::Arg::with_name("inputs")
             .required(true)
             .index(1)
-            .help("Merge log files from that directory.")
+            .multiple(true)
+            .help("Merge these files, `-` being stdin, `@list.txt` being the paths listed in that file, \
+                   and the log files found in these directories")
         )
         .arg(clap::Arg::with_name("output-file")
             .long("output-file")
@@ -152,10 +344,51 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
             .value_name("file")
             .help("Write results to that file (default is stdout)")
         )
+        .arg(clap::Arg::with_name("output-format")
+            .long("output-format")
+            .value_name("format")
+            .possible_values(logtools::output::OUTPUT_FORMATS)
+            .default_value("text")
+            .help("Output the log entries as they are, or as JSON (one array), NDJSON, CSV or logfmt records \
+                   of the source, the timestamp in ISO 8601, the named groups of the entry pattern, and the full text")
+        )
         .arg(clap::Arg::with_name("print-debug")
             .long("print-debug")
             .help("Enable printing of debug info to stderr.")
         )
+        .arg(clap::Arg::with_name("format")
+             .long("format")
+             .value_name("name")
+             .help(logtools::formats::FORMAT_HELP)
+        )
+        .arg(clap::Arg::with_name("source-format")
+             .long("source-format")
+             .value_name("glob=format")
+             .multiple(true)
+             .number_of_values(1)
+             .help("Parse the files that match the glob with this named format, or `auto`, e.g. `nginx/*.log=nginx`. \
+                    The glob may match the path or its end. The first matching rule wins, other files use \
+                    --format or --entry-pattern and --timestamp-pattern.")
+        )
+        .arg(clap::Arg::with_name("source-timezone")
+             .long("source-timezone")
+             .value_name("glob=timezone")
+             .multiple(true)
+             .number_of_values(1)
+             .help("The timestamps of the files that match the glob are in this timezone, either a UTC offset \
+                    like `+02:00` or a name like `Europe/Berlin`, e.g. `host2=America/New_York`. Timestamps of other \
+                    files are taken as UTC, and timestamps with a UTC offset, e.g. of the nginx format, keep it. \
+                    The glob may match the path or any part of it, the first matching rule wins.")
+        )
+        .arg(clap::Arg::with_name("source-skew")
+             .long("source-skew")
+             .value_name("glob=duration")
+             .multiple(true)
+             .number_of_values(1)
+             .help("Add this duration to the timestamps of the files that match the glob, to correct a clock that \
+                    is off, e.g. `host3=+2.4s` for a host whose clock is 2.4s behind. The glob may match the path \
+                    or any part of it, the first matching rule wins.")
+        )
         .arg(clap::Arg::with_name("entry-pattern")
              .long("entry-pattern")
              .value_name("regex")
@@ -168,25 +401,113 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
              .help("Pattern for parsing contents of the 'timestamp' regex capture group into a date/time structure. For syntax see Rust's chrono::format::strftime docs.")
              .default_value(r"%Y-%m-%d %H:%M:%S,%3f")
         )
+        .arg(clap::Arg::with_name("json-timestamp-key")
+             .long("json-timestamp-key")
+             .value_name("path")
+             .help("For NDJSON files, i.e. one JSON object per line, take the timestamp from this key, \
+                    nested keys separated with dots, e.g. `meta.time` (default is the first of `timestamp`, \
+                    `@timestamp`, `time`, `ts`). NDJSON files are recognized by their first character being `{` \
+                    and can be merged with plain text ones.")
+        )
+        .arg(clap::Arg::with_name("since")
+             .long("since")
+             .takes_value(true)
+             .value_name("time")
+             .help(&SINCE_HELP)
+        )
+        .arg(clap::Arg::with_name("until")
+             .long("until")
+             .takes_value(true)
+             .value_name("time")
+             .help(&UNTIL_HELP)
+        )
+        .arg(clap::Arg::with_name("follow")
+             .long("follow")
+             .help("Keep reading the log files as they grow, like `tail -F`, merging the new entries as they come. \
+                   An entry is only output once every file has a newer one, or had nothing appended \
+                   for --idle-timeout.")
+        )
+        .arg(clap::Arg::with_name("on-error")
+             .long("on-error")
+             .value_name("policy")
+             .possible_values(logtools::errors::ERROR_POLICIES)
+             .default_value("warn")
+             .help("What to do about files that can't be read: leave them out quietly, leave them out with a warning, \
+                    or stop. The files left out are listed at the end.")
+        )
+        .arg(clap::Arg::with_name("reorder-window")
+             .long("reorder-window")
+             .value_name("duration")
+             .conflicts_with("follow")
+             .help("Re-sort the entries of each input that are up to this much out of order, e.g. `500ms` for \
+                    multi-threaded loggers. Entries that are later than that are reported and output as they come \
+                    (default is no re-sorting).")
+        )
+        .arg(clap::Arg::with_name("idle-timeout")
+             .long("idle-timeout")
+             .value_name("duration")
+             .default_value("1s")
+             .help("With --follow, output what's pending once nothing was appended for this long, e.g. `500ms`")
+        )
+        .arg(clap::Arg::with_name("encoding")
+             .long("encoding")
+             .value_name("encoding")
+             .possible_values(logtools::inputs::ENCODINGS)
+             .default_value("utf-8")
+             .help(logtools::inputs::ENCODING_HELP)
+        )
         .arg(clap::Arg::with_name("include-glob")
              .long("include-glob")
              .short("i")
              .value_name("glob")
-             .help("Only merge files that match this glob")
-             .default_value(r"*.{log,log.[0-9]*}")
+             .multiple(true)
+             .number_of_values(1)
+             .help("In directories, only merge files that match this glob, or any of them when given several times")
+             .default_value(logtools::inputs::DEFAULT_INCLUDE_GLOB)
         )
         .arg(clap::Arg::with_name("exclude-glob")
              .long("exclude-glob")
              .short("x")
              .takes_value(true)
              .value_name("glob")
-             .help("Skip files that match this glob")
+             .multiple(true)
+             .number_of_values(1)
+             .help("In directories, skip files that match this glob, or any of them when given several times")
+        )
+        .arg(clap::Arg::with_name("group-rotated")
+             .long("group-rotated")
+             .short("R")
+             .conflicts_with("follow")
+             .help("Read rotated files like app.log.2.gz, app.log.1 and app.log, or app.log.20200901, one after the other, \
+                   oldest first, as a single source labelled app.log")
         )
         .arg(clap::Arg::with_name("truncate-last-dir")
              .long("truncate-last-dir")
              .short("S")
              .help("For output log entry source path, truncate to last directory entry")
         )
+        .arg(clap::Arg::with_name("source-label")
+             .long("source-label")
+             .value_name("template")
+             .conflicts_with_all(&["truncate-last-dir", "no-entry-source"])
+             .help("Label entries by this template instead of the path, e.g. `{host} {stem}`. {path} is the path, \
+                    {dir} the directory name, {file} the file name, {stem} the file name up to the first dot, and every \
+                    named group of --source-label-regex is a placeholder too. {host} is the `host` group, or else {dir}. \
+                    Labels without `:` can still be skipped with `loggrep -L`.")
+        )
+        .arg(clap::Arg::with_name("source-label-regex")
+             .long("source-label-regex")
+             .value_name("regex")
+             .requires("source-label")
+             .help("Match this regex against each path for the named groups used in --source-label, \
+                    e.g. `/(?P<host>[^/.]+)\\.example\\.com/`")
+        )
+        .arg(clap::Arg::with_name("source-label-width")
+             .long("source-label-width")
+             .value_name("width")
+             .help("Pad source labels with spaces to this many characters, cutting longer ones, or to the longest \
+                    label for `auto`, so that the entries line up")
+        )
         .arg(clap::Arg::with_name("no-entry-source")
              .long("no-entry-source")
              .short("s")
@@ -195,7 +516,48 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
         )
 }
 
-fn fatal<S: Into<String>>(s: S) -> ! {
-    eprintln!("{}", s.into());
-    std::process::exit(1)
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follow_without_reorder_window() {
+        let cli = cli_app().get_matches_from_safe(vec!["logmerge", "--follow", "a.log", "b.log"]).unwrap();
+        assert!(cli.is_present("follow"));
+        assert_eq!(cli.value_of("reorder-window"), None);
+        assert!(cli_app().get_matches_from_safe(vec!["logmerge", "--follow", "--reorder-window", "1s", "a.log"]).is_err());
+        assert!(cli_app().get_matches_from_safe(vec!["logmerge", "--reorder-window", "1s", "a.log"]).is_ok());
+    }
+
+    /// Merges the `files`, given by name and content, with `options`, returning the exit status and the output.
+    fn merged(name: &str, files: &[(&str, &str)], options: &[&str]) -> (i32, String) {
+        let dir = std::env::temp_dir().join(format!("logtools-merge-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("merged.out");
+        let mut args = vec!["logmerge".into(), "-s".into(), "-o".into(), output.clone().into_os_string()];
+        for (file, content) in files {
+            std::fs::write(dir.join(file), content).unwrap();
+            args.push(dir.join(file).into_os_string());
+        }
+        args.extend(options.iter().map(|it| it.into()));
+        let status = main0(args).unwrap();
+        let merged = std::fs::read_to_string(&output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        (status, merged)
+    }
+
+    #[test]
+    fn source_timezones_leave_ndjson_timestamps_alone() {
+        let files = [
+            ("app.log", "2020-09-01 12:00:00,000 local noon in +02:00\n2020-09-01 14:00:00,000 local 14:00 in +02:00\n"),
+            ("events.json", "{\"timestamp\":\"2020-09-01T11:00:00Z\",\"msg\":\"11:00 UTC\"}\n"),
+        ];
+        assert_eq!(merged("timezones", &files, &["--source-timezone", "*=+02:00"]), (0, [
+            "2020-09-01 12:00:00,000 local noon in +02:00",
+            "{\"timestamp\":\"2020-09-01T11:00:00Z\",\"msg\":\"11:00 UTC\"}",
+            "2020-09-01 14:00:00,000 local 14:00 in +02:00",
+            "",
+        ].join("\n")));
+    }
 }
@@ -1,4 +1,3 @@
-use std::io::BufReader;
 use std::io::BufWriter;
 use std::fs::File;
 use std::io::Write;
@@ -6,10 +5,14 @@ use std::io::Write;
 use either::{Left, Right};
 
 use logentry::entry::*;
+use logtools::errors::is_broken_pipe;
+use logtools::inputs::{Encoding, Input};
+use logtools::timerange::has_offset;
 
 fn main() {
     std::process::exit(match main0() {
         Ok(_) => 0,
+        Err(e) if is_broken_pipe(&*e) => 0,
         Err(e) => {
             eprintln!("ERROR: {:?}", e);
             1
@@ -27,23 +30,19 @@ fn main0<'a>() -> std::result::Result<(), Box<dyn std::error::Error>> {
         None => Right(BufWriter::new(stdout.lock())),
     };
 
-    let stdin = std::io::stdin();
     let input = match cli.value_of("input-file") {
-        Some(filename) => Left(BufReader::new(File::open(filename)?)),
-        None => Right(stdin.lock()),
-    };
+        Some(filename) => Input::File(filename.into()),
+        None => Input::Stdin,
+    }.open()?;
 
-    let timestamp_pattern = Some(cli.value_of("timestamp-pattern").unwrap());
+    let (entry_pattern, timestamp_pattern) = logtools::formats::patterns_from_cli(&cli, true, cli.value_of("input-file").map(std::path::Path::new))?;
 
-    let entry_regex = regex::Regex::new(cli.value_of("entry-pattern").unwrap())?;
-    use bstr::io::BufReadExt;
-    let entries = LogEntryIterator::new(
+    let entry_regex = regex::Regex::new(&entry_pattern)?;
+    let encoding = Encoding::from_name(cli.value_of("encoding").unwrap())?;
+    let mut entries = LogEntryIterator::new(
         &entry_regex,
-        timestamp_pattern,
-        Box::new(input.byte_lines().filter_map(|x| match x {
-            Err(e) => { eprintln!("ERROR: reading input: {}", e); None },
-            Ok(line) => Some(String::from_utf8_lossy(line.as_ref()).to_string()),
-        }))
+        None,
+        logtools::inputs::lines(input, encoding),
     );
 
     let offset_text = cli.value_of("offset").unwrap();
@@ -55,21 +54,56 @@ fn main0<'a>() -> std::result::Result<(), Box<dyn std::error::Error>> {
         Ok(it) => chrono::Duration::hours(it),
     };
     entries
-        .for_each(|it| {
-            let offset_zdt: Zdt = it.zdt.unwrap() + offset_duration;
-            let captured_timestamp = entry_regex.captures(&it.text).unwrap().name("timestamp").unwrap().as_str();
-            let new_timestamp = offset_zdt.format(timestamp_pattern.unwrap()).to_string();
-            let new_text = it.text.replace(captured_timestamp, new_timestamp.as_str());
-            output.write(format!(
+        .try_for_each(|it| {
+            let captured_timestamp = entry_regex.captures(&it.text).and_then(|it| it.name("timestamp"));
+            let new_text = match captured_timestamp {
+                Some(captured) => match shift_timestamp(captured.as_str(), &timestamp_pattern, offset_duration) {
+                    Some(new_timestamp) => format!("{}{}{}", &it.text[..captured.start()], new_timestamp, &it.text[captured.end()..]),
+                    None => it.text.clone(),
+                },
+                None => it.text.clone(),
+            };
+            output.write_all(format!(
                 "{}\n",
                 new_text
-            ).as_bytes()).unwrap();
-        })
-    ;
+            ).as_bytes())
+        })?;
+    output.flush()?;
 
     Ok(())
 }
 
+lazy_static::lazy_static! {
+    static ref OFFSET_SUFFIX: regex::Regex = regex::Regex::new(r"(?:Z|[+-]\d{2}(?::?\d{2})?)$").unwrap();
+}
+
+/// The timestamp `text` of `timestamp_pattern` moved by `offset`.  A UTC offset at the end of the timestamp,
+/// like the ones of the nginx, syslog and iso8601 formats, is kept as it's written, so that the time moves
+/// in the entry's own timezone.  None when the timestamp doesn't parse.
+fn shift_timestamp(text: &str, timestamp_pattern: &str, offset: chrono::Duration) -> Option<String> {
+    let shift = |text: &str, pattern: &str| chrono::NaiveDateTime::parse_from_str(text, pattern).ok()
+        .and_then(|it| it.checked_add_signed(offset))
+        .map(|it| it.format(pattern).to_string());
+    if !has_offset(timestamp_pattern) {
+        return shift(text, timestamp_pattern);
+    }
+    let pattern = without_offset(timestamp_pattern);
+    let with_offset = OFFSET_SUFFIX.find(text).and_then(|suffix| {
+        shift(&text[..suffix.start()], &pattern).map(|it| format!("{}{}", it, suffix.as_str()))
+    });
+    // The offset may be left out, as in the timestamps of the iso8601 format.
+    with_offset.or_else(|| shift(text, &pattern))
+}
+
+/// The pattern with its trailing UTC offset left out, see `shift_timestamp`.
+fn without_offset(timestamp_pattern: &str) -> String {
+    let pattern = timestamp_pattern.replace("%+", "%Y-%m-%dT%H:%M:%S%.f");
+    ["%:z", "%#z", "%z"].iter()
+        .find_map(|it| pattern.strip_suffix(it))
+        .unwrap_or(&pattern)
+        .to_string()
+}
+
 fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
     clap::App::new("logoffset")
         .version(clap::crate_version!())
@@ -87,6 +121,11 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
             .index(1)
             .help("Read log entries from this file (default is stdin).")
         )
+        .arg(clap::Arg::with_name("format")
+             .long("format")
+             .value_name("name")
+             .help(logtools::formats::FORMAT_HELP)
+        )
         .arg(clap::Arg::with_name("entry-pattern")
              .long("entry-pattern")
              .takes_value(true)
@@ -94,6 +133,13 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
              .help("Regex matching the first line of a log entry. Must have `timestamp` and `message` capturing groups.")
              .default_value(r"^(?P<timestamp>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2},\d{3}) (?P<message>.*)")
         )
+        .arg(clap::Arg::with_name("encoding")
+             .long("encoding")
+             .value_name("encoding")
+             .possible_values(logtools::inputs::ENCODINGS)
+             .default_value("utf-8")
+             .help(logtools::inputs::ENCODING_HELP)
+        )
         .arg(clap::Arg::with_name("offset")
             .long("offset-hours")
             .takes_value(true)
@@ -108,3 +154,25 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
             .default_value(r"%Y-%m-%d %H:%M:%S,%3f")
         )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shifted(text: &str, format: &str, hours: i64) -> Option<String> {
+        let pattern = &logtools::formats::builtin_formats()[format].timestamp_pattern;
+        shift_timestamp(text, pattern, chrono::Duration::hours(hours))
+    }
+
+    #[test]
+    fn timestamps_keep_their_offset() {
+        assert_eq!(shifted("2020-09-01T23:30:00.123+02:00", "iso8601", 1), Some("2020-09-02T00:30:00.123+02:00".to_string()));
+        assert_eq!(shifted("2020-09-01T12:00:00Z", "iso8601", -3), Some("2020-09-01T09:00:00Z".to_string()));
+        assert_eq!(shifted("2020-09-01T12:00:00-0500", "iso8601", 2), Some("2020-09-01T14:00:00-0500".to_string()));
+        assert_eq!(shifted("2020-09-01T12:00:00", "iso8601", 2), Some("2020-09-01T14:00:00".to_string()));
+        assert_eq!(shifted("01/Sep/2020:12:00:00 +0200", "nginx", 13), Some("02/Sep/2020:01:00:00 +0200".to_string()));
+        assert_eq!(shifted("2020-09-01T12:00:00.5+02:00", "syslog", 1), Some("2020-09-01T13:00:00.500+02:00".to_string()));
+        assert_eq!(shifted("2020-09-01 12:00:00,000", "log4j", 1), Some("2020-09-01 13:00:00,000".to_string()));
+        assert_eq!(shifted("yesterday", "nginx", 1), None);
+    }
+}
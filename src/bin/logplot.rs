@@ -1,8 +1,11 @@
+use std::io::Write;
+
 use plotters::{prelude as pp};
 use plotters::drawing::IntoDrawingArea;
 use plotters::style::IntoFont;
 
-use std::io::BufRead;
+use logtools::errors::ignore_broken_pipe;
+
 
 #[derive(Debug)]
 struct Data {
@@ -28,6 +31,11 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
             .value_name("file")
             .help("Write SVG chart to this file")
         )
+        .arg(clap::Arg::with_name("format")
+             .long("format")
+             .value_name("name")
+             .help(logtools::formats::FORMAT_HELP)
+        )
         .arg(clap::Arg::with_name("entry-pattern")
              .long("entry-pattern")
              .value_name("regex")
@@ -40,6 +48,13 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
              .help("Pattern for parsing contents of the 'timestamp' regex capture group into a date/time structure. For syntax see Rust's chrono::format::strftime docs.")
              .default_value(r"%d/%m/%Y %H:%M:%S.%3f")
         )
+        .arg(clap::Arg::with_name("encoding")
+             .long("encoding")
+             .value_name("encoding")
+             .possible_values(logtools::inputs::ENCODINGS)
+             .default_value("utf-8")
+             .help(logtools::inputs::ENCODING_HELP)
+        )
         .arg(clap::Arg::with_name("chart-width")
             .long("chart-width")
             .short("w")
@@ -70,19 +85,16 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let output_filename = cli.value_of("output-file").unwrap();
 
     let data = {
-        let input = std::io::BufReader::new(
-            std::fs::File::open(
-                cli.value_of("input-file").unwrap()
-            ).expect("Can't open input file")
-        );
-        let entry_regex = regex::Regex::new(cli.value_of("entry-pattern").unwrap()).expect("Invalid regex for `entry-pattern`");
-        let entries = logentry::entry::LogEntryIterator::new(
+        let input = logtools::inputs::open_file(
+            std::path::Path::new(cli.value_of("input-file").unwrap())
+        ).expect("Can't open input file");
+        let (entry_pattern, timestamp_pattern) = logtools::formats::patterns_from_cli(&cli, true, Some(std::path::Path::new(cli.value_of("input-file").unwrap())))?;
+        let entry_regex = regex::Regex::new(&entry_pattern).expect("Invalid regex for `entry-pattern`");
+        let encoding = logtools::inputs::Encoding::from_name(cli.value_of("encoding").unwrap()).unwrap();
+        let entries = logtools::record::log_entries(
             &entry_regex,
-            Some(cli.value_of("timestamp-pattern").unwrap()),
-            Box::new(input.lines().filter_map(|it| match it {
-                Err(e) => { eprintln!("ERROR: reading input: {}", e); None },
-                Ok(line) => Some(line),
-            }))
+            Some(timestamp_pattern.as_str()),
+            logtools::inputs::lines(input, encoding),
         );
         let mut out = Vec::new();
         for entry in entries {
@@ -108,7 +120,7 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let width = width - (width % 8);
         let height = cli.value_of("chart-height").unwrap().parse::<u32>().expect("Invalid chart-height value");
         let height = height - (height % 8);
-        println!("Chart is {}x{}", width, height);
+        ignore_broken_pipe(writeln!(std::io::stdout(), "Chart is {}x{}", width, height))?;
         pp::SVGBackend::new(output_filename, (width, height)).into_drawing_area()
     };
     root.fill(&pp::WHITE)?;
@@ -120,8 +132,8 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
         Some(it) => it.parse::<usize>().unwrap(),
         None => data.iter().map(|it| it.reqs).max().unwrap(),
     };
-    println!("X: {} to {}", dt_from, dt_to);
-    println!("Y: {} to {}", 0, max_value);
+    ignore_broken_pipe(writeln!(std::io::stdout(), "X: {} to {}", dt_from, dt_to))?;
+    ignore_broken_pipe(writeln!(std::io::stdout(), "Y: {} to {}", 0, max_value))?;
     let mut chart = pp::ChartBuilder::on(&root)
         .caption(caption, ("sans-serif", 30).into_font())
         .margin(5)
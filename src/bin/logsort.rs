@@ -46,6 +46,18 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
             .value_name("file")
             .help("Write sorted log entries into this file")
         )
+        .arg(clap::Arg::with_name("encoding")
+             .long("encoding")
+             .value_name("encoding")
+             .possible_values(logtools::inputs::ENCODINGS)
+             .default_value("utf-8")
+             .help(logtools::inputs::ENCODING_HELP)
+        )
+        .arg(clap::Arg::with_name("format")
+             .long("format")
+             .value_name("name")
+             .help(logtools::formats::FORMAT_HELP)
+        )
         .arg(clap::Arg::with_name("entry-pattern")
              .long("entry-pattern")
              .value_name("regex")
@@ -61,36 +73,35 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
         ;
     let cli = clap.get_matches();
             
-    let input = std::io::BufReader::new(
-        std::fs::File::open(
-            cli.value_of("input-file").unwrap()
-        ).expect("Can't open input file")
-    );
+    let input = logtools::inputs::open_file(
+        std::path::Path::new(cli.value_of("input-file").unwrap())
+    ).expect("Can't open input file");
     let output_filename = cli.value_of("output-file").unwrap();
     let mut output = std::io::BufWriter::new(match std::fs::File::create(output_filename) {
         Err(e) => panic!("Can't open output file `{}` for writing: {}", output_filename, e),
         Ok(it) => it,
     });
     
-    let entry_regex = regex::Regex::new(cli.value_of("entry-pattern").unwrap()).expect("Invalid regex for `entry-pattern`");
-    use std::io::BufRead;
-    let entries = logentry::entry::LogEntryIterator::new(
+    let (entry_pattern, timestamp_pattern) = logtools::formats::patterns_from_cli(&cli, true, Some(std::path::Path::new(cli.value_of("input-file").unwrap())))?;
+    let entry_regex = regex::Regex::new(&entry_pattern).expect("Invalid regex for `entry-pattern`");
+    let encoding = logtools::inputs::Encoding::from_name(cli.value_of("encoding").unwrap()).unwrap();
+    let entries = logtools::record::log_entries(
         &entry_regex,
-        Some(cli.value_of("timestamp-pattern").unwrap()),
-        Box::new(input.lines().filter_map(|it| match it {
-            Err(e) => { eprintln!("ERROR: reading input: {}", e); None },
-            Ok(line) => Some(line),
-        }))
+        Some(timestamp_pattern.as_str()),
+        logtools::inputs::lines(input, encoding),
     );
     let entries = entries.map(|it| Entry { text: it.text, zdt: it.zdt.unwrap() });
     let sorter = external_sort::ExternalSorter::new(1024 * 1024, None);
-    let sorted_iter = sorter.sort(entries).unwrap();
+    let mut sorted_iter = sorter.sort(entries).unwrap();
 
     use std::io::Write;
-    sorted_iter.for_each(|entry| {
-        output.write(entry.unwrap().text.as_bytes()).expect("writing to output");
-        output.write("\n".as_bytes()).expect("writing to output");
-    });
+    let written = sorted_iter
+        .try_for_each(|entry| {
+            output.write_all(entry.unwrap().text.as_bytes())?;
+            output.write_all("\n".as_bytes())
+        })
+        .and_then(|_| output.flush());
+    logtools::errors::ignore_broken_pipe(written)?;
 
     Ok(())
 }
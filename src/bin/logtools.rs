@@ -0,0 +1,114 @@
+use std::io::Write;
+use std::path::Path;
+
+use logtools::errors::{ErrorPolicy, ReadErrors, Skipped, is_broken_pipe};
+use logtools::formats::{detect_format, load_formats};
+use logtools::inputs::{Encoding, checked_lines, open_file};
+
+fn main() {
+    std::process::exit(match main0() {
+        Ok(it) => it,
+        Err(e) if is_broken_pipe(&*e) => 0,
+        Err(e) => {
+            eprintln!("ERROR: {:?}", e);
+            1
+        }
+    });
+}
+
+/// Returns the exit status, see `detect`.
+fn main0() -> std::result::Result<i32, Box<dyn std::error::Error>> {
+    let cli_app = cli_app();
+    let cli = cli_app.get_matches();
+
+    match cli.subcommand() {
+        ("detect", Some(cli)) => detect(cli),
+        _ => Err("missing subcommand, see --help".into()),
+    }
+}
+
+/// 0 when the format of every file was detected, 2 when some files were left out, see --on-error.
+fn detect(cli: &clap::ArgMatches) -> std::result::Result<i32, Box<dyn std::error::Error>> {
+    let encoding = Encoding::from_name(cli.value_of("encoding").unwrap())?;
+    let count = match cli.value_of("lines").unwrap().parse::<usize>() {
+        Err(e) => return Err(format!("--lines `{}` is not a non-negative integer: {}", cli.value_of("lines").unwrap(), e).into()),
+        Ok(it) => it,
+    };
+    let formats = load_formats()?;
+    let mut skipped = Skipped::new(ErrorPolicy::from_name(cli.value_of("on-error").unwrap())?);
+    let read_errors = ReadErrors::default();
+    let mut detected_all = true;
+    let mut stdout = std::io::stdout();
+    for file in cli.values_of("input-files").unwrap() {
+        let reader = match skipped.check(file, open_file(Path::new(file)))? {
+            Some(it) => it,
+            None => continue,
+        };
+        let lines = checked_lines(reader, encoding, file.to_string(), read_errors.clone()).take(count).collect::<Vec<_>>();
+        skipped.check_read(&read_errors)?;
+        match detect_format(&lines, formats.clone()) {
+            None => {
+                eprintln!("ERROR: none of the known formats fits {}", file);
+                detected_all = false;
+            },
+            Some(it) => {
+                writeln!(stdout, "{}: {} ({} of {} lines begin a log entry)", file, it.name, it.matched_lines, lines.len())?;
+                writeln!(
+                    stdout,
+                    "    --entry-pattern {} --timestamp-pattern {}",
+                    shell_quote(&format!("^{}", it.format.entry_pattern)),
+                    shell_quote(&it.format.timestamp_pattern),
+                )?;
+            },
+        }
+    }
+    skipped.report();
+    match (detected_all, skipped.is_empty()) {
+        (false, _) => Err("couldn't detect the format of every file".into()),
+        (true, true) => Ok(0),
+        (true, false) => Ok(2),
+    }
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
+    clap::App::new("logtools")
+        .version(clap::crate_version!())
+        .author(clap::crate_authors!())
+        .about("Helpers for the other log tools")
+        .subcommand(clap::SubCommand::with_name("detect")
+            .about("Detect the format of log files among the built-in ones and those in formats.toml, \
+                    printing the --entry-pattern and --timestamp-pattern to use")
+            .arg(clap::Arg::with_name("input-files")
+                .required(true)
+                .index(1)
+                .multiple(true)
+                .help("Detect the format of these files")
+            )
+            .arg(clap::Arg::with_name("lines")
+                .long("lines")
+                .short("n")
+                .value_name("lines")
+                .default_value("100")
+                .help("Look at this many lines from the beginning of each file")
+            )
+            .arg(clap::Arg::with_name("encoding")
+                .long("encoding")
+                .value_name("encoding")
+                .possible_values(logtools::inputs::ENCODINGS)
+                .default_value("utf-8")
+                .help(logtools::inputs::ENCODING_HELP)
+            )
+            .arg(clap::Arg::with_name("on-error")
+                .long("on-error")
+                .value_name("policy")
+                .possible_values(logtools::errors::ERROR_POLICIES)
+                .default_value("warn")
+                .help("What to do about files that can't be read: leave them out quietly, leave them out with a warning, \
+                       or stop. The files left out are listed at the end.")
+            )
+        )
+}
@@ -1,5 +1,3 @@
-use std::io::BufRead;
-use std::io::BufReader;
 use std::io::BufWriter;
 use std::fs::File;
 use std::io::Write;
@@ -7,10 +5,13 @@ use std::io::Write;
 use either::{Left, Right};
 
 use logentry::entry::*;
+use logtools::errors::is_broken_pipe;
+use logtools::inputs::{Encoding, Input};
 
 fn main() {
     std::process::exit(match main0() {
         Ok(_) => 0,
+        Err(e) if is_broken_pipe(&*e) => 0,
         Err(e) => {
             eprintln!("ERROR: {:?}", e);
             1
@@ -28,20 +29,18 @@ fn main0<'a>() -> std::result::Result<(), Box<dyn std::error::Error>> {
         None => Right(BufWriter::new(stdout.lock())),
     };
 
-    let stdin = std::io::stdin();
     let input = match cli.value_of("input-file") {
-        Some(filename) => Left(BufReader::new(File::open(filename)?)),
-        None => Right(stdin.lock()),
-    };
+        Some(filename) => Input::File(filename.into()),
+        None => Input::Stdin,
+    }.open()?;
 
-    let entry_regex = regex::Regex::new(cli.value_of("entry-pattern").unwrap())?;
+    let (entry_pattern, _) = logtools::formats::patterns_from_cli(&cli, true, cli.value_of("input-file").map(std::path::Path::new))?;
+    let entry_regex = regex::Regex::new(&entry_pattern)?;
+    let encoding = Encoding::from_name(cli.value_of("encoding").unwrap())?;
     let entries = LogEntryIterator::new(
         &entry_regex,
         None,
-        Box::new(input.lines().filter_map(|x| match x {
-            Err(e) => { eprintln!("ERROR: reading input: {}", e); None },
-            Ok(line) => Some(line),
-        }))
+        logtools::inputs::lines(input, encoding),
     );
 
     //let started = std::time::Instant::now();
@@ -70,8 +69,9 @@ fn main0<'a>() -> std::result::Result<(), Box<dyn std::error::Error>> {
     };
 
     for (line, count) in inverse_sorted.into_iter() {
-        output.write(format!("{:8} {}", count, line).as_bytes()).expect("failed to write to output");
+        output.write_all(format!("{:8} {}", count, line).as_bytes())?;
     }
+    output.flush()?;
 
     //println!("Elapsed: {:?}", started.elapsed());
 
@@ -95,6 +95,18 @@ fn cli_app<'a, 'b>() -> clap::App<'a, 'b> {
             .index(1)
             .help("Read log entries from this file (default is stdin).")
         )
+        .arg(clap::Arg::with_name("encoding")
+             .long("encoding")
+             .value_name("encoding")
+             .possible_values(logtools::inputs::ENCODINGS)
+             .default_value("utf-8")
+             .help(logtools::inputs::ENCODING_HELP)
+        )
+        .arg(clap::Arg::with_name("format")
+             .long("format")
+             .value_name("name")
+             .help(logtools::formats::FORMAT_HELP)
+        )
         .arg(clap::Arg::with_name("entry-pattern")
              .long("entry-pattern")
              .takes_value(true)
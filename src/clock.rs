@@ -0,0 +1,143 @@
+//! Correcting the timestamps of a source for its timezone and the drift of its clock, so that sources of
+//! different hosts can be merged.
+//!
+//! Timestamps are parsed as if they were UTC.  A source's timezone turns them into actual UTC, then its skew
+//! is added, e.g. `+2.4s` for a host whose clock is 2.4 seconds behind.
+
+use chrono::{FixedOffset, TimeZone};
+use logentry::entry::Zdt;
+
+use crate::inputs::{SourceGlob, parse_source_rule};
+use crate::timerange::parse_duration;
+
+#[derive(Clone, Copy)]
+pub enum Timezone {
+    Fixed(FixedOffset),
+    Named(chrono_tz::Tz),
+}
+
+impl Timezone {
+    /// Takes `UTC`, an offset like `+02:00`, `-0500` or `+2`, or a name like `Europe/Berlin`.
+    pub fn parse(text: &str) -> Result<Timezone, String> {
+        if text.eq_ignore_ascii_case("utc") || text == "Z" {
+            return Ok(Timezone::Fixed(FixedOffset::east_opt(0).unwrap()));
+        }
+        if let Some(captured) = OFFSET_REGEX.captures(text) {
+            let hours = captured[2].parse::<i32>().unwrap();
+            let minutes = captured.get(3).map_or(0, |it| it.as_str().parse::<i32>().unwrap());
+            let seconds = match &captured[1] {
+                "-" => -(hours * 3600 + minutes * 60),
+                _ => hours * 3600 + minutes * 60,
+            };
+            return FixedOffset::east_opt(seconds)
+                .map(Timezone::Fixed)
+                .ok_or_else(|| format!("UTC offset `{}` is out of range", text));
+        }
+        text.parse::<chrono_tz::Tz>()
+            .map(Timezone::Named)
+            .map_err(|_| format!("`{}` is neither a UTC offset like `+02:00` nor a timezone like `Europe/Berlin`", text))
+    }
+
+    /// The UTC time of a local time that was parsed as UTC.  Times that a DST change skips or repeats are taken
+    /// with the offset before the change.
+    pub fn to_utc(&self, zdt: Zdt) -> Zdt {
+        let local = zdt.naive_utc();
+        match self {
+            Timezone::Fixed(it) => it.from_local_datetime(&local).earliest().map(|it| it.with_timezone(&chrono::Utc)),
+            Timezone::Named(it) => it.from_local_datetime(&local).earliest()
+                .or_else(|| it.from_local_datetime(&(local + chrono::Duration::hours(1))).earliest())
+                .map(|it| it.with_timezone(&chrono::Utc)),
+        }.unwrap_or(zdt)
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref OFFSET_REGEX: regex::Regex = regex::Regex::new(r"^(?:UTC|GMT)?([+-])(\d{1,2})(?::?(\d{2}))?$").unwrap();
+}
+
+/// The timezone and skew of a source.
+#[derive(Clone, Copy)]
+pub struct ClockCorrection {
+    pub timezone: Option<Timezone>,
+    pub skew: chrono::Duration,
+}
+
+impl Default for ClockCorrection {
+    fn default() -> Self {
+        ClockCorrection { timezone: None, skew: chrono::Duration::zero() }
+    }
+}
+
+impl ClockCorrection {
+    pub fn apply(&self, zdt: Zdt) -> Zdt {
+        self.timezone.map_or(zdt, |it| it.to_utc(zdt)) + self.skew
+    }
+}
+
+/// `glob=timezone` and `glob=skew` rules, see `inputs::SourceGlob`.  The first matching rule of each kind applies.
+pub struct ClockRules {
+    timezones: Vec<(SourceGlob, Timezone)>,
+    skews: Vec<(SourceGlob, chrono::Duration)>,
+}
+
+impl ClockRules {
+    pub fn from_cli(cli: &clap::ArgMatches, timezone_name: &str, skew_name: &str) -> Result<ClockRules, String> {
+        let mut timezones = Vec::new();
+        for rule in cli.values_of(timezone_name).into_iter().flatten() {
+            let (glob, value) = parse_source_rule(rule)?;
+            timezones.push((glob, Timezone::parse(value)?));
+        }
+        let mut skews = Vec::new();
+        for rule in cli.values_of(skew_name).into_iter().flatten() {
+            let (glob, value) = parse_source_rule(rule)?;
+            skews.push((glob, parse_duration(value)?));
+        }
+        Ok(ClockRules { timezones, skews })
+    }
+
+    pub fn correction(&self, path: &std::path::Path) -> ClockCorrection {
+        ClockCorrection {
+            timezone: self.timezones.iter().find(|it| it.0.matches(path)).map(|it| it.1),
+            skew: self.skews.iter().find(|it| it.0.matches(path)).map_or(chrono::Duration::zero(), |it| it.1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> Zdt {
+        chrono::Utc.from_utc_datetime(&chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap())
+    }
+
+    #[test]
+    fn timezones() {
+        assert!(Timezone::parse("UTC").is_ok());
+        assert!(Timezone::parse("+0530").is_ok());
+        assert!(Timezone::parse("Mars/Olympus_Mons").is_err());
+        assert!(Timezone::parse("+25:00").is_err());
+        assert_eq!(Timezone::parse("-05:00").unwrap().to_utc(at("2020-09-01 12:00:00")), at("2020-09-01 17:00:00"));
+        assert_eq!(Timezone::parse("+2").unwrap().to_utc(at("2020-09-01 12:00:00")), at("2020-09-01 10:00:00"));
+    }
+
+    #[test]
+    fn to_utc_across_dst_changes() {
+        let berlin = Timezone::parse("Europe/Berlin").unwrap();
+        assert_eq!(berlin.to_utc(at("2020-01-15 12:00:00")), at("2020-01-15 11:00:00"));
+        assert_eq!(berlin.to_utc(at("2020-07-15 12:00:00")), at("2020-07-15 10:00:00"));
+        // 02:30 doesn't exist on the day clocks go forward, and happens twice on the day they go back.
+        assert_eq!(berlin.to_utc(at("2020-03-29 01:59:59")), at("2020-03-29 00:59:59"));
+        assert_eq!(berlin.to_utc(at("2020-03-29 02:30:00")), at("2020-03-29 01:30:00"));
+        assert_eq!(berlin.to_utc(at("2020-03-29 03:00:00")), at("2020-03-29 01:00:00"));
+        assert_eq!(berlin.to_utc(at("2020-10-25 02:30:00")), at("2020-10-25 00:30:00"));
+        assert_eq!(berlin.to_utc(at("2020-10-25 03:00:00")), at("2020-10-25 02:00:00"));
+    }
+
+    #[test]
+    fn corrections() {
+        let correction = ClockCorrection { timezone: Some(Timezone::parse("+01:00").unwrap()), skew: chrono::Duration::seconds(2) };
+        assert_eq!(correction.apply(at("2020-09-01 12:00:00")), at("2020-09-01 11:00:02"));
+        assert_eq!(ClockCorrection::default().apply(at("2020-09-01 12:00:00")), at("2020-09-01 12:00:00"));
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+
+pub enum Context<T> {
+    Entry(T),
+    /// Goes between two groups of entries that weren't adjacent in the input.
+    Separator,
+}
+
+/// Yields the items selected by the predicate, along with up to `before` preceding and `after`
+/// following items, like grep's `-B`/`-A`.  Separators are only yielded when there is some context.
+pub struct ContextIterator<I: Iterator, F> {
+    inner: I,
+    selected: F,
+    before: usize,
+    after: usize,
+    preceding: VecDeque<(usize, I::Item)>,
+    pending: VecDeque<Context<I::Item>>,
+    after_remaining: usize,
+    max_selected: Option<usize>,
+    selected_count: usize,
+    last_yielded: Option<usize>,
+    index: usize,
+}
+
+impl<I, F> ContextIterator<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> bool,
+{
+    pub fn new(inner: I, before: usize, after: usize, selected: F) -> Self {
+        ContextIterator {
+            inner,
+            selected,
+            before,
+            after,
+            preceding: VecDeque::with_capacity(before + 1),
+            pending: VecDeque::new(),
+            after_remaining: 0,
+            max_selected: None,
+            selected_count: 0,
+            last_yielded: None,
+            index: 0,
+        }
+    }
+
+    /// Stops after this many selected items and the context following the last of them, like grep's `-m`.
+    /// The rest of the inner iterator isn't read.
+    pub fn with_max_selected(mut self, max: Option<usize>) -> Self {
+        self.max_selected = max;
+        self
+    }
+}
+
+impl<I, F> Iterator for ContextIterator<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> bool,
+{
+    type Item = Context<I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(it) = self.pending.pop_front() {
+                return Some(it);
+            }
+            let at_max = self.max_selected == Some(self.selected_count);
+            if at_max && self.after_remaining == 0 {
+                return None;
+            }
+            let item = self.inner.next()?;
+            let index = self.index;
+            self.index += 1;
+
+            // Past the maximum, selected items only go on as the context after the last one, the way grep does.
+            if !at_max && (self.selected)(&item) {
+                self.selected_count += 1;
+                let first = self.preceding.front().map_or(index, |it| it.0);
+                let has_context = self.before > 0 || self.after > 0;
+                if has_context && self.last_yielded.is_some_and(|last| first > last + 1) {
+                    self.pending.push_back(Context::Separator);
+                }
+                self.pending.extend(self.preceding.drain(..).map(|it| Context::Entry(it.1)));
+                self.pending.push_back(Context::Entry(item));
+                self.after_remaining = self.after;
+                self.last_yielded = Some(index);
+            } else if self.after_remaining > 0 {
+                self.pending.push_back(Context::Entry(item));
+                self.after_remaining -= 1;
+                self.last_yielded = Some(index);
+            } else if self.before > 0 {
+                self.preceding.push_back((index, item));
+                if self.preceding.len() > self.before {
+                    self.preceding.pop_front();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The items of `0..count` selected by `selected`, with context, as `0 1 -- 5`.
+    fn render(count: usize, before: usize, after: usize, max: Option<usize>, selected: &[usize]) -> String {
+        ContextIterator::new(0..count, before, after, |it| selected.contains(it))
+            .with_max_selected(max)
+            .map(|it| match it {
+                Context::Entry(it) => it.to_string(),
+                Context::Separator => "--".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn without_context_only_selected_items() {
+        assert_eq!(render(10, 0, 0, None, &[2, 7]), "2 7");
+    }
+
+    #[test]
+    fn before_and_after() {
+        assert_eq!(render(10, 2, 1, None, &[4]), "2 3 4 5");
+        assert_eq!(render(10, 2, 0, None, &[1]), "0 1");
+        assert_eq!(render(10, 0, 3, None, &[8]), "8 9");
+    }
+
+    #[test]
+    fn separators_only_between_groups_that_are_not_adjacent() {
+        assert_eq!(render(20, 1, 1, None, &[2, 10]), "1 2 3 -- 9 10 11");
+        assert_eq!(render(20, 1, 1, None, &[2, 5]), "1 2 3 4 5 6");
+        assert_eq!(render(20, 1, 1, None, &[2, 4]), "1 2 3 4 5");
+    }
+
+    #[test]
+    fn overlapping_context_is_yielded_once() {
+        assert_eq!(render(10, 2, 2, None, &[3, 4]), "1 2 3 4 5 6");
+    }
+
+    #[test]
+    fn max_selected_keeps_the_context_after_the_last() {
+        assert_eq!(render(10, 0, 2, Some(1), &[2, 3, 8]), "2 3 4");
+        assert_eq!(render(10, 0, 0, Some(2), &[2, 3, 8]), "2 3");
+        assert_eq!(render(10, 0, 0, Some(0), &[2]), "");
+    }
+}
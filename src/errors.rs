@@ -0,0 +1,105 @@
+//! Dealing with inputs that can't be read, and with the output being closed early, e.g. by `| head`.
+
+use std::cell::RefCell;
+use std::io::ErrorKind;
+use std::rc::Rc;
+
+pub const ERROR_POLICIES: &[&str] = &["skip", "warn", "fail"];
+
+/// What to do about an input that can't be read.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ErrorPolicy {
+    /// Leave it out, only mentioning it in the summary at the end.
+    Skip,
+    /// Leave it out, reporting it right away and in the summary at the end.
+    Warn,
+    /// Stop with the error.
+    Fail,
+}
+
+impl ErrorPolicy {
+    /// Takes one of `ERROR_POLICIES`.
+    pub fn from_name(name: &str) -> Result<ErrorPolicy, String> {
+        match name {
+            "skip" => Ok(ErrorPolicy::Skip),
+            "warn" => Ok(ErrorPolicy::Warn),
+            "fail" => Ok(ErrorPolicy::Fail),
+            _ => Err(format!("unsupported error policy `{}`, expected one of {}", name, ERROR_POLICIES.join(", "))),
+        }
+    }
+}
+
+/// The inputs left out by an `ErrorPolicy`, to be listed once more at the end.
+pub struct Skipped {
+    policy: ErrorPolicy,
+    inputs: Vec<String>,
+}
+
+impl Skipped {
+    pub fn new(policy: ErrorPolicy) -> Self {
+        Skipped { policy, inputs: Vec::new() }
+    }
+
+    /// The value of reading `input`, or None when it's left out.  Under `ErrorPolicy::Fail`, the error.
+    pub fn check<T>(&mut self, input: &str, result: std::io::Result<T>) -> std::io::Result<Option<T>> {
+        match (result, self.policy) {
+            (Ok(it), _) => Ok(Some(it)),
+            (Err(e), ErrorPolicy::Fail) => Err(e),
+            (Err(e), policy) => {
+                if policy == ErrorPolicy::Warn {
+                    eprintln!("WARNING: skipping, {}", e);
+                }
+                self.inputs.push(input.to_string());
+                Ok(None)
+            },
+        }
+    }
+
+    /// Like `check`, for the inputs that failed while they were read, see `ReadErrors`.
+    pub fn check_read(&mut self, errors: &ReadErrors) -> std::io::Result<()> {
+        for (input, e) in errors.take() {
+            self.check(&input, Err::<(), _>(e))?;
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Lists the inputs left out on stderr, if any.
+    pub fn report(&self) {
+        if !self.inputs.is_empty() {
+            eprintln!("WARNING: skipped {} file(s) that couldn't be read:", self.inputs.len());
+            self.inputs.iter().for_each(|it| eprintln!("    {}", it));
+        }
+    }
+}
+
+/// Errors of inputs that are read lazily, e.g. a corrupt gzip stream found halfway through, collected for
+/// `Skipped::check_read` once the reading is done.  Clones share the errors.
+#[derive(Clone, Default)]
+pub struct ReadErrors(Rc<RefCell<Vec<(String, std::io::Error)>>>);
+
+impl ReadErrors {
+    pub fn push(&self, input: String, e: std::io::Error) {
+        self.0.borrow_mut().push((input, e));
+    }
+
+    pub fn take(&self) -> Vec<(String, std::io::Error)> {
+        self.0.borrow_mut().drain(..).collect()
+    }
+}
+
+/// Whether the error is the reader of the output having gone away, which just ends the output early.
+pub fn is_broken_pipe(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.downcast_ref::<std::io::Error>().is_some_and(|it| it.kind() == ErrorKind::BrokenPipe)
+}
+
+/// Turns a closed output into success, see `is_broken_pipe`.
+pub fn ignore_broken_pipe(result: std::io::Result<()>) -> std::io::Result<()> {
+    match result {
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        it => it,
+    }
+}
@@ -0,0 +1,443 @@
+//! Boolean filter expressions, e.g. `(ERROR or WARN) and "OrderService" and not /timeout \d+ms/`.
+//!
+//! Atoms:
+//! - `word` - a bare word, matched verbatim
+//! - `"some text"` - a quoted string, matched verbatim; `\"` and `\\` are unescaped
+//! - `/regex/` - a regex; `\/` stands for a literal slash
+//! - `name=value`, `name!=value` - a named field of the entry (a capture group of the entry pattern)
+//!   is, or is not, equal to a word or a quoted string, or matches a `/regex/`
+//! - `name=~value`, `name!~value` - a named field contains, or doesn't contain, a word or a quoted
+//!   string, or matches a `/regex/`
+//!
+//! `==` is the same as `=`, and operators may be surrounded with whitespace, e.g. `level == "error"`.
+//! Fields are the named capture groups of the entry pattern, or the values of NDJSON entries.  A bare name
+//! is a field when it's a capture group, when the entry is NDJSON, or when the operator is `==` or spaced out,
+//! as in `level == "error"`.  Any other field is written with a leading dot, e.g. `.http.status=~/^5/`, so that
+//! a bare word like `user=bob` is still matched as text.  The negated conditions don't match entries that lack
+//! the field.
+//!
+//! Operators, from lowest to highest precedence: `or` (`||`), `and` (`&&`), `not` (`!`).
+//! Parentheses group.  To match one of the keywords literally, quote it.
+
+use crate::matchers::*;
+
+/// Parses a standalone field condition, e.g. `level=ERROR` or `thread=~/pool-\d+/`.  Any name is a field here.
+pub fn parse_field(spec: &str) -> Result<Box<dyn Matcher>, String> {
+    let mut tokens = tokenize(spec, &|_| true)?;
+    match (tokens.pop(), tokens.is_empty()) {
+        (Some(Token::Field(name, op, value)), true) => field_matcher(name, op, *value),
+        _ => Err(format!("`{}` is not a field condition like `name=value` or `name=~/regex/`", spec)),
+    }
+}
+
+/// `options` apply to the words, strings and regexes that are matched against the entry text,
+/// not to field conditions.  `field_names` are the names that are fields without a leading dot,
+/// i.e. the named capture groups of the entry pattern, or None for any name, as for NDJSON entries.
+pub fn parse(expression: &str, options: PatternOptions, field_names: Option<&[&str]>) -> Result<Box<dyn Matcher>, String> {
+    let is_field = |name: &str| match field_names {
+        Some(it) => it.contains(&name),
+        None => true,
+    };
+    let tokens = tokenize(expression, &is_field)?;
+    let mut parser = Parser { tokens: tokens.into_iter().peekable(), options };
+    let matcher = parser.parse_or()?;
+    match parser.peek() {
+        None => Ok(matcher),
+        Some(token) => Err(format!("unexpected {} in expression `{}`", token, expression)),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Verbatim(String),
+    Regex(String),
+    Field(String, FieldOp, Box<Token>),
+}
+
+#[derive(Debug, PartialEq)]
+enum FieldOp {
+    Equals,
+    NotEquals,
+    Contains,
+    NotContains,
+}
+
+impl std::fmt::Display for FieldOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            FieldOp::Equals => "=",
+            FieldOp::NotEquals => "!=",
+            FieldOp::Contains => "=~",
+            FieldOp::NotContains => "!~",
+        })
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::And => write!(f, "`and`"),
+            Token::Or => write!(f, "`or`"),
+            Token::Not => write!(f, "`not`"),
+            Token::Verbatim(it) => write!(f, "string `{}`", it),
+            Token::Regex(it) => write!(f, "regex `/{}/`", it),
+            Token::Field(name, op, _) => write!(f, "field condition `{}{}...`", name, op),
+        }
+    }
+}
+
+fn tokenize(expression: &str, is_field: &dyn Fn(&str) -> bool) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => { chars.next(); },
+            '(' => { chars.next(); tokens.push(Token::Open); },
+            ')' => { chars.next(); tokens.push(Token::Close); },
+            '!' => { chars.next(); tokens.push(Token::Not); },
+            '"' => tokens.push(read_quoted(&mut chars, expression)?),
+            '/' => tokens.push(read_regex(&mut chars, expression)?),
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' || c == '.' || c == '@' || c == '-' {
+                        word.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let name = match word.strip_prefix('.') {
+                    Some(it) if !it.is_empty() => Some(it),
+                    _ if !word.is_empty() && (is_field(&word) || is_comparison(&chars)) => Some(word.as_str()),
+                    _ => None,
+                };
+                if let Some(name) = name {
+                    if let Some(op) = read_field_op(&mut chars) {
+                        let name = name.to_string();
+                        let value = match chars.peek() {
+                            Some('"') => read_quoted(&mut chars, expression)?,
+                            Some('/') => read_regex(&mut chars, expression)?,
+                            _ => Token::Verbatim(read_word(&mut chars)),
+                        };
+                        tokens.push(Token::Field(name, op, Box::new(value)));
+                        continue;
+                    }
+                }
+                word.push_str(&read_word(&mut chars));
+                tokens.push(match word.as_str() {
+                    "and" | "&&" => Token::And,
+                    "or" | "||" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Verbatim(word),
+                });
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn read_word(chars: &mut Chars) -> String {
+    let mut word = String::new();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+            break;
+        }
+        word.push(c);
+        chars.next();
+    }
+    word
+}
+
+/// Whether an operator follows that can't be part of a bare word: `==`, or any operator after whitespace,
+/// as in `level == "error"`.
+fn is_comparison(chars: &Chars) -> bool {
+    let mut lookahead = chars.clone();
+    match lookahead.next() {
+        Some(c) if c.is_whitespace() => read_field_op(&mut lookahead).is_some(),
+        Some('=') => lookahead.peek() == Some(&'='),
+        _ => false,
+    }
+}
+
+/// Reads an operator along with the whitespace around it, so that both `level=ERROR` and `level == ERROR` work.
+fn read_field_op(chars: &mut Chars) -> Option<FieldOp> {
+    let mut lookahead = chars.clone();
+    skip_whitespace(&mut lookahead);
+    let op = match (lookahead.next(), lookahead.peek()) {
+        (Some('='), Some('~')) => FieldOp::Contains,
+        (Some('='), Some('=')) => FieldOp::Equals,
+        (Some('!'), Some('~')) => FieldOp::NotContains,
+        (Some('!'), Some('=')) => FieldOp::NotEquals,
+        (Some('='), _) => {
+            skip_whitespace(&mut lookahead);
+            *chars = lookahead;
+            return Some(FieldOp::Equals);
+        },
+        _ => return None,
+    };
+    lookahead.next();
+    skip_whitespace(&mut lookahead);
+    *chars = lookahead;
+    Some(op)
+}
+
+fn skip_whitespace(chars: &mut Chars) {
+    while chars.peek().is_some_and(|it| it.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn read_quoted(chars: &mut Chars, expression: &str) -> Result<Token, String> {
+    chars.next();
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            None => return Err(format!("unterminated string in expression `{}`", expression)),
+            Some('"') => break,
+            Some('\\') => match chars.next() {
+                Some(escaped @ '"') | Some(escaped @ '\\') => text.push(escaped),
+                Some(other) => { text.push('\\'); text.push(other); },
+                None => return Err(format!("unterminated string in expression `{}`", expression)),
+            },
+            Some(other) => text.push(other),
+        }
+    }
+    Ok(Token::Verbatim(text))
+}
+
+fn read_regex(chars: &mut Chars, expression: &str) -> Result<Token, String> {
+    chars.next();
+    let mut pattern = String::new();
+    loop {
+        match chars.next() {
+            None => return Err(format!("unterminated regex in expression `{}`", expression)),
+            Some('/') => break,
+            Some('\\') => match chars.next() {
+                Some('/') => pattern.push('/'),
+                Some(other) => { pattern.push('\\'); pattern.push(other); },
+                None => return Err(format!("unterminated regex in expression `{}`", expression)),
+            },
+            Some(other) => pattern.push(other),
+        }
+    }
+    Ok(Token::Regex(pattern))
+}
+
+fn compile_regex(pattern: &str) -> Result<regex::Regex, String> {
+    compile_regex_with(pattern, PatternOptions::default())
+}
+
+fn compile_regex_with(pattern: &str, options: PatternOptions) -> Result<regex::Regex, String> {
+    regex::Regex::new(&options.regex(pattern)).map_err(|e| format!("invalid regex `/{}/` in expression: {}", pattern, e))
+}
+
+fn field_matcher(name: String, op: FieldOp, value: Token) -> Result<Box<dyn Matcher>, String> {
+    let value_matcher: Box<dyn Matcher> = match (&op, value) {
+        (_, Token::Regex(pattern)) => Box::new(RegexMatcher(compile_regex(&pattern)?)),
+        (FieldOp::Equals, Token::Verbatim(text)) | (FieldOp::NotEquals, Token::Verbatim(text)) => Box::new(EqualsMatcher(text)),
+        (_, Token::Verbatim(text)) => Box::new(VerbatimMatcher(text)),
+        (_, other) => return Err(format!("unexpected {} after `{}{}`", other, name, op)),
+    };
+    Ok(Box::new(FieldMatcher(name, match op {
+        FieldOp::Equals | FieldOp::Contains => value_matcher,
+        FieldOp::NotEquals | FieldOp::NotContains => Box::new(NotMatcher(value_matcher)),
+    })))
+}
+
+struct Parser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+    options: PatternOptions,
+}
+
+impl Parser {
+    fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.tokens.next()
+    }
+
+    fn parse_or(&mut self) -> Result<Box<dyn Matcher>, String> {
+        let mut operands = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            operands.push(self.parse_and()?);
+        }
+        Ok(match operands.len() {
+            1 => operands.pop().unwrap(),
+            _ => Box::new(AnyMatcher(operands)),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Box<dyn Matcher>, String> {
+        let mut operands = vec![self.parse_not()?];
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            operands.push(self.parse_not()?);
+        }
+        Ok(match operands.len() {
+            1 => operands.pop().unwrap(),
+            _ => Box::new(AllMatcher(operands)),
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<Box<dyn Matcher>, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Box::new(NotMatcher(self.parse_not()?)));
+        }
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<Box<dyn Matcher>, String> {
+        match self.next() {
+            None => Err("unexpected end of expression".to_string()),
+            Some(Token::Open) => {
+                let matcher = self.parse_or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(matcher),
+                    _ => Err("missing `)` in expression".to_string()),
+                }
+            },
+            Some(Token::Verbatim(text)) => {
+                let matcher: Box<dyn Matcher> = match self.options.is_plain() {
+                    true => Box::new(VerbatimMatcher(text)),
+                    false => Box::new(RegexMatcher(regex::Regex::new(&self.options.verbatim_regex(&text))
+                        .map_err(|e| format!("invalid text `{}` in expression: {}", text, e))?)),
+                };
+                Ok(scoped(matcher, self.options.scope))
+            },
+            Some(Token::Regex(pattern)) => Ok(scoped(
+                Box::new(RegexMatcher(compile_regex_with(&pattern, self.options)?)),
+                self.options.scope,
+            )),
+            Some(Token::Field(name, op, value)) => field_matcher(name, op, *value),
+            Some(token) => Err(format!("unexpected {} in expression", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::json::JsonFields;
+    use super::*;
+
+    fn fields(pairs: &[(&str, &str)]) -> JsonFields {
+        JsonFields(pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect::<BTreeMap<_, _>>())
+    }
+
+    fn matches(expression: &str, field_names: &[&str], text: &str, fields: &JsonFields) -> bool {
+        parse(expression, PatternOptions::default(), Some(field_names)).unwrap().matches_entry(text, fields)
+    }
+
+    #[test]
+    fn boolean_operators_and_precedence() {
+        let none = fields(&[]);
+        let text = "ERROR OrderService: request timeout 500ms";
+        assert!(matches(r#"(ERROR or WARN) and "OrderService" and not /timeout \d+s\b/"#, &[], text, &none));
+        assert!(!matches(r#"(ERROR or WARN) and "OrderService" and not /timeout \d+ms/"#, &[], text, &none));
+        // `and` binds tighter than `or`, and `not` tighter than `and`.
+        assert!(matches("WARN and Payment or ERROR", &[], text, &none));
+        assert!(!matches("WARN and (Payment or ERROR)", &[], text, &none));
+        assert!(matches("not WARN and ERROR", &[], text, &none));
+        assert!(!matches("not (WARN or ERROR)", &[], text, &none));
+        assert!(matches("!WARN && (INFO || ERROR)", &[], text, &none));
+        assert!(matches("not not ERROR", &[], text, &none));
+    }
+
+    #[test]
+    fn quoted_keywords_strings_and_regexes() {
+        let none = fields(&[]);
+        assert!(matches(r#""and" and "or""#, &[], "this and that or those", &none));
+        assert!(matches(r#"not "not""#, &[], "something else", &none));
+        assert!(matches(r#""say \"hi\"""#, &[], r#"they say "hi""#, &none));
+        assert!(matches(r"/GET \/api\/v\d/", &[], "GET /api/v2/orders", &none));
+        assert!(!matches(r"/GET \/api\/v\d/", &[], "GET /web/v2/orders", &none));
+    }
+
+    #[test]
+    fn options_apply_to_text_not_fields() {
+        let options = PatternOptions { ignore_case: true, ..PatternOptions::default() };
+        let entry = fields(&[("level", "ERROR")]);
+        assert!(parse("error and level=ERROR", options, Some(&["level"])).unwrap().matches_entry("an ERROR", &entry));
+        assert!(!parse("error and level=error", options, Some(&["level"])).unwrap().matches_entry("an ERROR", &entry));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        for expression in &["", "ERROR and", "(ERROR or WARN", "ERROR)", r#""unterminated"#, "/unterminated", "level=~/(/", "not"] {
+            assert!(parse(expression, PatternOptions::default(), Some(&["level"])).is_err(), "{}", expression);
+        }
+        // Too long to compile, with the options making a regex of the text.
+        let options = PatternOptions { ignore_case: true, ..PatternOptions::default() };
+        assert!(parse(&"x".repeat(1 << 22), options, None).is_err());
+    }
+
+    #[test]
+    fn word_with_equals_sign_is_text_unless_a_field() {
+        let none = fields(&[]);
+        assert!(matches("user=bob", &[], "login user=bob", &none));
+        assert!(matches("user!=bob", &[], "login user!=bob", &none));
+        assert!(!matches("user=bob", &["user"], "login user=bob", &none));
+        assert!(matches("user=bob", &["user"], "login", &fields(&[("user", "bob")])));
+    }
+
+    #[test]
+    fn comparisons_are_fields() {
+        let entry = fields(&[("level", "error"), ("msg", "disk full")]);
+        assert!(matches(r#"level == "error""#, &[], "", &entry));
+        assert!(matches(r#"level=="error" and msg =~ disk"#, &[], "", &entry));
+        assert!(!matches(r#"level != error"#, &[], "level != error", &entry));
+    }
+
+    #[test]
+    fn any_name_is_a_field_of_ndjson_entries() {
+        let record = r#"{"ts":"2020-09-01T10:00:00Z","level":"error","msg":"disk full"}"#;
+        let value = serde_json::from_str::<serde_json::Value>(record).unwrap();
+        let entry = crate::json::json_fields(&value).unwrap();
+        let matches = |expression: &str| parse(expression, PatternOptions::default(), None).unwrap().matches_entry(record, &entry);
+        assert!(matches(r#"level == "error""#));
+        assert!(matches("level=error"));
+        assert!(!matches("level=warn"));
+        assert!(matches("disk and not level=warn"));
+    }
+
+    #[test]
+    fn leading_dot_is_a_field() {
+        let entry = fields(&[("level", "error"), ("http.status", "503")]);
+        assert!(matches(r#".level == "error" and .http.status=~/^5/"#, &[], "", &entry));
+        assert!(!matches(".level=warn", &[], ".level=warn", &entry));
+        assert!(matches(".NET", &[], "a .NET app", &entry));
+    }
+
+    #[test]
+    fn negated_conditions_need_the_field() {
+        let entry = fields(&[("level", "INFO")]);
+        let other = fields(&[("thread", "main")]);
+        assert!(matches("level!=ERROR", &["level"], "", &entry));
+        assert!(!matches("level!=ERROR", &["level"], "", &other));
+        assert!(!matches("level!~ERR", &["level"], "", &other));
+        assert!(matches("not level=ERROR", &["level"], "", &other));
+    }
+
+    #[test]
+    fn standalone_field_condition() {
+        let entry = fields(&[("thread", "pool-3")]);
+        assert!(parse_field(r"thread=~/pool-\d+/").unwrap().matches_entry("", &entry));
+        assert!(parse_field("thread != main").unwrap().matches_entry("", &entry));
+        assert!(parse_field("thread").is_err());
+    }
+}
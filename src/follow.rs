@@ -0,0 +1,378 @@
+//! Following growing log files, like `tail -F`.
+//!
+//! A multiline log entry is only known to be complete once the first line of the next entry arrives,
+//! so the last entry of a followed file is held back until either that happens, or nothing new
+//! has been read for the idle timeout.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use crate::clock::ClockCorrection;
+use crate::inputs::Encoding;
+use crate::record::Record;
+use crate::timerange::parse_timestamp;
+
+/// How often a file that has no new lines is checked for growth, truncation and rotation.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reads lines of the file at `path` on a background thread, from the beginning and then as they
+/// are appended.  When the file is truncated, reading restarts from its beginning.  When the file
+/// is replaced, e.g. rotated, the new file at the same path is read from its beginning.  While there's
+/// no file to read, it's waited for, with a warning.  Only works for ASCII-compatible encodings.
+pub fn follow_file(path: PathBuf, encoding: Encoding) -> Receiver<String> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || tail(path, encoding, sender));
+    receiver
+}
+
+/// Reads stdin lines on a background thread.
+pub fn follow_stdin(encoding: Encoding) -> Receiver<String> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let stdin = Box::new(BufReader::new(std::io::stdin()));
+        for line in crate::inputs::lines(stdin, encoding) {
+            if sender.send(line).is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+/// The device and inode of a file, to tell when a followed path was rotated to a new file.
+type FileId = Option<(u64, u64)>;
+
+#[cfg(unix)]
+fn file_id(metadata: &std::fs::Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &std::fs::Metadata) -> FileId {
+    None
+}
+
+fn tail(path: PathBuf, encoding: Encoding, sender: Sender<String>) {
+    let mut current: Option<(BufReader<std::fs::File>, FileId)> = None;
+    let mut position = 0u64;
+    let mut partial = Vec::new();
+    let mut warned = false;
+    loop {
+        let (reader, id) = match current.as_mut() {
+            Some(it) => it,
+            None => {
+                match std::fs::File::open(&path) {
+                    Err(e) => {
+                        if !warned {
+                            eprintln!("WARNING: can't open {}, waiting for it: {}", path.to_string_lossy(), e);
+                            warned = true;
+                        }
+                        std::thread::sleep(POLL_INTERVAL);
+                        continue;
+                    },
+                    Ok(file) => {
+                        let id = file.metadata().ok().as_ref().and_then(file_id);
+                        position = 0;
+                        partial.clear();
+                        warned = false;
+                        current.get_or_insert((BufReader::new(file), id))
+                    },
+                }
+            },
+        };
+
+        match reader.read_until(b'\n', &mut partial) {
+            Err(e) => {
+                eprintln!("ERROR: reading {}: {}", path.to_string_lossy(), e);
+                std::thread::sleep(POLL_INTERVAL);
+            },
+            Ok(0) => {
+                std::thread::sleep(POLL_INTERVAL);
+                match std::fs::metadata(&path) {
+                    Err(_) => {},
+                    Ok(metadata) => {
+                        let replaced = id.is_some() && file_id(&metadata) != *id;
+                        if replaced {
+                            current = None;
+                        } else if metadata.len() < position {
+                            if reader.seek(SeekFrom::Start(0)).is_err() {
+                                current = None;
+                            }
+                            position = 0;
+                            partial.clear();
+                        }
+                    },
+                }
+            },
+            Ok(read) => {
+                position += read as u64;
+                if partial.last() != Some(&b'\n') {
+                    // Half-written line, wait for the rest of it.
+                    continue;
+                }
+                partial.pop();
+                if partial.last() == Some(&b'\r') {
+                    partial.pop();
+                }
+                let line = encoding.decode(&partial);
+                partial.clear();
+                if sender.send(line).is_err() {
+                    return;
+                }
+            },
+        }
+    }
+}
+
+/// Groups followed lines into log entries, see the module docs.
+pub struct FollowEntries {
+    lines: Receiver<String>,
+    entry_regex: regex::Regex,
+    timestamp_pattern: Option<String>,
+    idle_timeout: Duration,
+    clock: ClockCorrection,
+    pending: Option<String>,
+}
+
+impl FollowEntries {
+    pub fn new(lines: Receiver<String>, entry_regex: regex::Regex, timestamp_pattern: Option<String>, idle_timeout: Duration) -> Self {
+        FollowEntries { lines, entry_regex, timestamp_pattern, idle_timeout, clock: ClockCorrection::default(), pending: None }
+    }
+
+    /// Corrects the timestamps for the timezone and skew of the source.
+    pub fn with_clock(mut self, clock: ClockCorrection) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn record(&self, text: String) -> Record {
+        let zdt = self.timestamp_pattern.as_ref()
+            .and_then(|it| parse_timestamp(&self.entry_regex, &text, it))
+            .map(|it| self.clock.apply(it));
+        Record { text, zdt, json: None }
+    }
+}
+
+impl Iterator for FollowEntries {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        loop {
+            match self.lines.recv_timeout(self.idle_timeout) {
+                Ok(line) => {
+                    if self.entry_regex.is_match(&line) {
+                        if let Some(previous) = self.pending.replace(line) {
+                            return Some(self.record(previous));
+                        }
+                    } else {
+                        match self.pending.as_mut() {
+                            Some(it) => {
+                                it.push('\n');
+                                it.push_str(&line);
+                            },
+                            None => self.pending = Some(line),
+                        }
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(it) = self.pending.take() {
+                        return Some(self.record(it));
+                    }
+                },
+                Err(RecvTimeoutError::Disconnected) => {
+                    return self.pending.take().map(|it| self.record(it));
+                },
+            }
+        }
+    }
+}
+
+/// Merges followed sources in timestamp order.  The earliest queued entry is yielded once every source either
+/// has an entry queued, so that it's known which one is the earliest, or is caught up, i.e. produced nothing
+/// for the idle timeout or ended.  A quiet source thus holds the others back for no longer than that.
+/// Yields entries along with their source index.
+pub struct FollowMerge {
+    /// Entries of each source, None once the source ended.
+    entries: Receiver<(usize, Option<Record>)>,
+    queues: Vec<VecDeque<Record>>,
+    last_received: Vec<Instant>,
+    ended: Vec<bool>,
+    idle_timeout: Duration,
+}
+
+/// What a `FollowMerge` can do at a given time.
+enum Poll {
+    /// The next entry, or None once every source ended.
+    Ready(Option<(Record, usize)>),
+    /// Nothing until a source produces an entry, or this time comes.
+    Pending(Instant),
+}
+
+impl FollowMerge {
+    pub fn new(sources: Vec<FollowEntries>) -> Self {
+        let idle_timeout = sources.iter().map(|it| it.idle_timeout).max().unwrap_or(POLL_INTERVAL);
+        let (sender, entries) = std::sync::mpsc::channel();
+        let merge = FollowMerge::with_entries(entries, sources.len(), idle_timeout, Instant::now());
+        for (index, source) in sources.into_iter().enumerate() {
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                for record in source {
+                    if sender.send((index, Some(record))).is_err() {
+                        return;
+                    }
+                }
+                let _ = sender.send((index, None));
+            });
+        }
+        merge
+    }
+
+    /// Merges the entries of `count` sources as they come from `entries`, taking them all as just heard from at `now`.
+    fn with_entries(entries: Receiver<(usize, Option<Record>)>, count: usize, idle_timeout: Duration, now: Instant) -> Self {
+        FollowMerge {
+            entries,
+            queues: (0..count).map(|_| VecDeque::new()).collect(),
+            last_received: vec![now; count],
+            ended: vec![false; count],
+            idle_timeout,
+        }
+    }
+
+    fn pop_earliest(&mut self) -> Option<(Record, usize)> {
+        let index = self.queues.iter()
+            .enumerate()
+            .filter_map(|(index, queue)| queue.front().map(|it| (it.zdt, index)))
+            .min()?
+            .1;
+        self.queues[index].pop_front().map(|it| (it, index))
+    }
+
+    /// When the last of the sources that may still produce an entry earlier than the queued ones is caught up,
+    /// or None when there are no such sources.
+    fn waiting_until(&self, now: Instant) -> Option<Instant> {
+        (0..self.queues.len())
+            .filter(|it| self.queues[*it].is_empty() && !self.ended[*it])
+            .map(|it| self.last_received[it] + self.idle_timeout)
+            .filter(|it| *it > now)
+            .max()
+    }
+
+    fn poll(&mut self, now: Instant) -> Poll {
+        let waiting_until = self.waiting_until(now);
+        if waiting_until.is_none() {
+            if let Some(it) = self.pop_earliest() {
+                return Poll::Ready(Some(it));
+            }
+            if self.ended.iter().all(|it| *it) {
+                return Poll::Ready(None);
+            }
+        }
+        Poll::Pending(waiting_until.unwrap_or(now + self.idle_timeout))
+    }
+
+    fn receive(&mut self, (index, record): (usize, Option<Record>), now: Instant) {
+        match record {
+            Some(it) => {
+                self.last_received[index] = now;
+                self.queues[index].push_back(it);
+            },
+            None => self.ended[index] = true,
+        }
+    }
+}
+
+impl Iterator for FollowMerge {
+    type Item = (Record, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let now = Instant::now();
+            let until = match self.poll(now) {
+                Poll::Ready(it) => return it,
+                Poll::Pending(it) => it,
+            };
+            match self.entries.recv_timeout(until - now) {
+                Ok(it) => self.receive(it, Instant::now()),
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => return self.pop_earliest(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{Sender, channel};
+
+    use super::*;
+
+    const IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+    fn source(lines: Receiver<String>) -> FollowEntries {
+        let entry_regex = regex::Regex::new(r"^(?P<timestamp>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}) ").unwrap();
+        FollowEntries::new(lines, entry_regex, Some("%Y-%m-%d %H:%M:%S".to_string()), IDLE_TIMEOUT)
+    }
+
+    /// The texts of the first `count` merged entries, or what there was after a few seconds.
+    fn merged(merge: FollowMerge, count: usize) -> Vec<String> {
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || merge.take(count).for_each(|it| { let _ = sender.send(it.0.text); }));
+        let mut texts = Vec::new();
+        while let Ok(it) = receiver.recv_timeout(Duration::from_secs(5)) {
+            texts.push(it);
+        }
+        texts
+    }
+
+    fn send(lines: &Sender<String>, texts: &[&str]) {
+        texts.iter().for_each(|it| lines.send(it.to_string()).unwrap());
+    }
+
+    #[test]
+    fn merges_in_timestamp_order() {
+        let (first, first_lines) = channel();
+        let (second, second_lines) = channel();
+        send(&first, &["2020-09-01 00:00:01 a", "2020-09-01 00:00:03 c", "  more of c"]);
+        send(&second, &["2020-09-01 00:00:02 b", "2020-09-01 00:00:04 d"]);
+        drop((first, second));
+        let merge = FollowMerge::new(vec![source(first_lines), source(second_lines)]);
+        assert_eq!(merged(merge, 4), vec![
+            "2020-09-01 00:00:01 a",
+            "2020-09-01 00:00:02 b",
+            "2020-09-01 00:00:03 c\n  more of c",
+            "2020-09-01 00:00:04 d",
+        ]);
+    }
+
+    /// The text of the entry the merge is ready with at `now`, or when it's waiting until.
+    fn poll(merge: &mut FollowMerge, now: Instant) -> Result<String, Instant> {
+        match merge.poll(now) {
+            Poll::Ready(it) => Ok(it.map_or("".to_string(), |it| it.0.text)),
+            Poll::Pending(it) => Err(it),
+        }
+    }
+
+    #[test]
+    fn quiet_source_does_not_hold_back_a_busy_one() {
+        let record = |text: &str| source(channel().1).record(text.to_string());
+        let start = Instant::now();
+        let (_sender, entries) = channel();
+        let mut merge = FollowMerge::with_entries(entries, 2, IDLE_TIMEOUT, start);
+        merge.receive((0, Some(record("2020-09-01 00:00:00 busy"))), start + IDLE_TIMEOUT / 10);
+        assert_eq!(poll(&mut merge, start + IDLE_TIMEOUT / 2), Err(start + IDLE_TIMEOUT));
+        assert_eq!(poll(&mut merge, start + IDLE_TIMEOUT), Ok("2020-09-01 00:00:00 busy".to_string()));
+        assert_eq!(poll(&mut merge, start + IDLE_TIMEOUT), Err(start + IDLE_TIMEOUT * 11 / 10));
+        merge.receive((0, Some(record("2020-09-01 00:00:01 busy"))), start + IDLE_TIMEOUT * 11 / 10);
+        merge.receive((0, Some(record("2020-09-01 00:00:02 busy"))), start + IDLE_TIMEOUT * 12 / 10);
+        assert_eq!(poll(&mut merge, start + IDLE_TIMEOUT * 12 / 10), Ok("2020-09-01 00:00:01 busy".to_string()));
+        assert_eq!(poll(&mut merge, start + IDLE_TIMEOUT * 12 / 10), Ok("2020-09-01 00:00:02 busy".to_string()));
+        // Once the busy source goes quiet too, it holds back the other one just the same.
+        merge.receive((1, Some(record("2020-09-01 00:00:03 quiet"))), start + IDLE_TIMEOUT * 13 / 10);
+        assert_eq!(poll(&mut merge, start + IDLE_TIMEOUT * 13 / 10), Err(start + IDLE_TIMEOUT * 22 / 10));
+        assert_eq!(poll(&mut merge, start + IDLE_TIMEOUT * 22 / 10), Ok("2020-09-01 00:00:03 quiet".to_string()));
+    }
+}
@@ -0,0 +1,266 @@
+//! Named log formats, so that `--format log4j` can stand in for `--entry-pattern` and `--timestamp-pattern`.
+//!
+//! Besides the built-in formats, more can be defined, or the built-in ones redefined, in
+//! `formats.toml` in the `logtools` config directory, e.g. `~/.config/logtools/formats.toml`:
+//!
+//! ```toml
+//! [myapp]
+//! entry-pattern = '(?P<timestamp>\d{2}\.\d{2}\.\d{4} \d{2}:\d{2}:\d{2}) (?P<level>\w+) (?P<message>.*)'
+//! timestamp-pattern = '%d.%m.%Y %H:%M:%S'
+//! ```
+//!
+//! Entry patterns are given without the leading `^`.  The fields of a format are the named capture
+//! groups of its entry pattern.
+//!
+//! With `--format auto`, the format is detected from the first lines of the input, see `detect_format`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::inputs::{Encoding, parse_glob_rule};
+
+pub const FORMAT_HELP: &str = "Use the entry and timestamp patterns of this named format: log4j, logback, nginx, syslog, iso8601, \
+    or one defined in the formats.toml file in the logtools config directory, e.g. ~/.config/logtools/formats.toml. \
+    `auto` picks the format that fits the first lines of the (first) input file best, see `logtools detect`. \
+    An explicitly given --entry-pattern or --timestamp-pattern takes precedence.";
+
+/// How many lines `--format auto` looks at.
+pub const DETECT_SAMPLE_LINES: usize = 100;
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Format {
+    /// Regex matching the first line of a log entry, without the leading `^`.
+    pub entry_pattern: String,
+    pub timestamp_pattern: String,
+}
+
+impl Format {
+    fn new(entry_pattern: &str, timestamp_pattern: &str) -> Format {
+        Format { entry_pattern: entry_pattern.to_string(), timestamp_pattern: timestamp_pattern.to_string() }
+    }
+
+    /// Names of the capture groups of the entry pattern.
+    pub fn fields(&self) -> Result<Vec<String>, String> {
+        let regex = regex::Regex::new(&self.entry_pattern).map_err(|e| e.to_string())?;
+        Ok(regex.capture_names().filter_map(|it| it.map(|it| it.to_string())).collect())
+    }
+}
+
+pub fn builtin_formats() -> BTreeMap<String, Format> {
+    let mut formats = BTreeMap::new();
+    // `%d{ISO8601} %-5p [%t] %c - %m%n`
+    formats.insert("log4j".to_string(), Format::new(
+        r"(?P<timestamp>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2},\d{3}) +(?P<level>[A-Z]+) +\[(?P<thread>[^\]]*)\] (?P<logger>\S+) - (?P<message>.*)",
+        "%Y-%m-%d %H:%M:%S,%3f",
+    ));
+    // `%d{yyyy-MM-dd HH:mm:ss.SSS} [%thread] %-5level %logger{36} - %msg%n`
+    formats.insert("logback".to_string(), Format::new(
+        r"(?P<timestamp>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}\.\d{3}) +\[(?P<thread>[^\]]*)\] +(?P<level>[A-Z]+) +(?P<logger>\S+) - (?P<message>.*)",
+        "%Y-%m-%d %H:%M:%S%.3f",
+    ));
+    // The `combined` access log format.
+    formats.insert("nginx".to_string(), Format::new(
+        r#"(?P<remote_addr>\S+) - (?P<remote_user>\S+) \[(?P<timestamp>[^\]]+)\] "(?P<request>[^"]*)" (?P<status>\d{3}) (?P<body_bytes_sent>\d+)(?P<message>.*)"#,
+        "%d/%b/%Y:%H:%M:%S %z",
+    ));
+    // rsyslog's `RSYSLOG_FileFormat`, as the traditional format has no year in its timestamps.
+    formats.insert("syslog".to_string(), Format::new(
+        r"(?P<timestamp>\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:\d{2})) (?P<host>\S+) (?P<program>[^\[:\s]+)(?:\[(?P<pid>\d+)\])?: (?P<message>.*)",
+        "%+",
+    ));
+    formats.insert("iso8601".to_string(), Format::new(
+        r"(?P<timestamp>\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:?\d{2})?) (?P<message>.*)",
+        "%Y-%m-%dT%H:%M:%S%.f%#z",
+    ));
+    formats
+}
+
+pub fn config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|it| it.join("logtools").join("formats.toml"))
+}
+
+/// The built-in formats along with the ones from the config file, which take precedence.
+pub fn load_formats() -> Result<BTreeMap<String, Format>, String> {
+    let mut formats = builtin_formats();
+    let path = match config_file() {
+        Some(it) if it.is_file() => it,
+        _ => return Ok(formats),
+    };
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("reading {}: {}", path.to_string_lossy(), e))?;
+    let configured: BTreeMap<String, Format> = toml::from_str(&content)
+        .map_err(|e| format!("parsing {}: {}", path.to_string_lossy(), e))?;
+    formats.extend(configured);
+    Ok(formats)
+}
+
+pub fn find_format(name: &str) -> Result<Format, String> {
+    let mut formats = load_formats()?;
+    match formats.remove(name) {
+        Some(it) => Ok(it),
+        None => Err(format!(
+            "unknown format `{}`, expected one of {}",
+            name,
+            formats.keys().map(|it| it.as_str()).collect::<Vec<_>>().join(", "),
+        )),
+    }
+}
+
+/// A format that fits the sampled lines, with the number of lines that begin a log entry with a parseable timestamp.
+pub struct Detected {
+    pub name: String,
+    pub format: Format,
+    pub matched_lines: usize,
+}
+
+/// Picks the format under which the most lines begin a log entry with a parseable timestamp, preferring
+/// the one with more fields on a tie.  None when no format fits any line.
+pub fn detect_format(lines: &[String], formats: BTreeMap<String, Format>) -> Option<Detected> {
+    formats.into_iter()
+        .filter_map(|(name, format)| {
+            let entry_regex = regex::Regex::new(&format!("^{}", format.entry_pattern)).ok()?;
+            let matched_lines = lines.iter()
+                .filter(|it| crate::timerange::parse_timestamp(&entry_regex, it, &format.timestamp_pattern).is_some())
+                .count();
+            let fields = entry_regex.captures_len();
+            Some((matched_lines, fields, Detected { name, format, matched_lines }))
+        })
+        .filter(|it| it.0 > 0)
+        .max_by_key(|it| (it.0, it.1))
+        .map(|it| it.2)
+}
+
+/// The first `count` lines of the file, decompressed and decoded.
+pub fn sample_lines(path: &Path, encoding: Encoding, count: usize) -> Result<Vec<String>, String> {
+    let reader = crate::inputs::open_file(path).map_err(|e| e.to_string())?;
+    Ok(crate::inputs::lines(reader, encoding).take(count).collect())
+}
+
+/// `--format auto` for the file at `path`.
+pub fn detect_file_format(path: &Path, encoding: Encoding) -> Result<Format, String> {
+    let lines = sample_lines(path, encoding, DETECT_SAMPLE_LINES)?;
+    match detect_format(&lines, load_formats()?) {
+        Some(it) => Ok(it.format),
+        None => Err(format!("none of the known formats fits {}, give --entry-pattern and --timestamp-pattern", path.to_string_lossy())),
+    }
+}
+
+/// The entry and timestamp patterns given with `--entry-pattern` and `--timestamp-pattern`, or else
+/// the ones of the `--format`, or else the defaults of these options.  Format entry patterns get
+/// prepended with `^` when `anchored`.  `--format auto` looks at the `sample` file.
+pub fn patterns_from_cli(cli: &clap::ArgMatches, anchored: bool, sample: Option<&Path>) -> Result<(String, String), String> {
+    let format = match (cli.value_of("format"), sample) {
+        (Some("auto"), Some(path)) => {
+            let encoding = Encoding::from_name(cli.value_of("encoding").unwrap_or("utf-8"))?;
+            Some(detect_file_format(path, encoding)?)
+        },
+        (Some("auto"), None) => return Err("--format auto needs an input file to look at".to_string()),
+        (Some(name), _) => Some(find_format(name)?),
+        (None, _) => None,
+    };
+    let entry_pattern = match (&format, cli.occurrences_of("entry-pattern")) {
+        (Some(format), 0) if anchored => format!("^{}", format.entry_pattern),
+        (Some(format), 0) => format.entry_pattern.clone(),
+        _ => cli.value_of("entry-pattern").unwrap().to_string(),
+    };
+    let timestamp_pattern = match (&format, cli.occurrences_of("timestamp-pattern")) {
+        (Some(format), 0) => format.timestamp_pattern.clone(),
+        _ => cli.value_of("timestamp-pattern").unwrap_or_default().to_string(),
+    };
+    Ok((entry_pattern, timestamp_pattern))
+}
+
+/// A `glob=format` rule giving the files that match the glob their own format, e.g. `nginx/*.log=nginx`.
+pub struct FormatRule {
+    glob: globset::GlobMatcher,
+    /// None for `auto`.
+    format: Option<Format>,
+}
+
+impl FormatRule {
+    pub fn parse(rule: &str) -> Result<FormatRule, String> {
+        let (glob, name) = parse_glob_rule(rule, "glob=format").map_err(|e| format!("format rule {}", e))?;
+        let format = match name {
+            "auto" => None,
+            _ => Some(find_format(name)?),
+        };
+        Ok(FormatRule { glob, format })
+    }
+
+    /// Whether the glob matches the path, or the path with some of its leading directories left out, so that
+    /// `nginx/*.log` matches `/var/log/nginx/access.log`.
+    pub fn matches(&self, path: &Path) -> bool {
+        let components = path.components().collect::<Vec<_>>();
+        (0..components.len()).any(|it| self.glob.is_match(components[it..].iter().collect::<PathBuf>()))
+    }
+}
+
+pub fn format_rules_from_cli(cli: &clap::ArgMatches, name: &str) -> Result<Vec<FormatRule>, String> {
+    cli.values_of(name).map_or(Ok(Vec::new()), |it| it.map(FormatRule::parse).collect())
+}
+
+/// The format of the first rule that matches the path, detecting it from the `sample` file for `auto`.
+/// None when no rule matches.
+pub fn rule_format(rules: &[FormatRule], path: &Path, sample: &Path, encoding: Encoding) -> Result<Option<Format>, String> {
+    match rules.iter().find(|it| it.matches(path)) {
+        Some(FormatRule { format: Some(format), .. }) => Ok(Some(format.clone())),
+        Some(FormatRule { format: None, .. }) => Ok(Some(detect_file_format(sample, encoding)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detected(lines: &[&str]) -> Option<String> {
+        let lines = lines.iter().map(|it| it.to_string()).collect::<Vec<_>>();
+        detect_format(&lines, builtin_formats()).map(|it| it.name)
+    }
+
+    #[test]
+    fn builtin_formats_are_detected() {
+        assert_eq!(detected(&["2020-09-01 12:00:00,123 INFO  [main] com.example.App - started"]), Some("log4j".to_string()));
+        assert_eq!(detected(&["2020-09-01 12:00:00.123 [main] INFO  com.example.App - started"]), Some("logback".to_string()));
+        assert_eq!(
+            detected(&[r#"127.0.0.1 - - [01/Sep/2020:12:00:00 +0200] "GET / HTTP/1.1" 200 612 "-" "curl/7.68.0""#]),
+            Some("nginx".to_string()),
+        );
+        assert_eq!(detected(&["2020-09-01T12:00:00.123+02:00 web1 sshd[4242]: Accepted publickey"]), Some("syslog".to_string()));
+        assert_eq!(detected(&["2020-09-01T12:00:00Z started"]), Some("iso8601".to_string()));
+        assert_eq!(detected(&["started at noon"]), None);
+    }
+
+    #[test]
+    fn the_format_fitting_more_lines_wins() {
+        let lines = [
+            "2020-09-01 12:00:00,123 INFO  [main] com.example.App - started",
+            "java.lang.IllegalStateException: boom",
+            "2020-09-01 12:00:01,456 ERROR [main] com.example.App - failed",
+            "2020-09-01 12:00:02.789 [main] INFO  com.example.App - logback style",
+        ];
+        let lines = lines.iter().map(|it| it.to_string()).collect::<Vec<_>>();
+        let detected = detect_format(&lines, builtin_formats()).unwrap();
+        assert_eq!((detected.name.as_str(), detected.matched_lines), ("log4j", 2));
+    }
+
+    #[test]
+    fn ties_go_to_the_format_with_more_fields() {
+        // The syslog line fits iso8601 as well, which has fewer fields.
+        assert_eq!(detected(&["2020-09-01T12:00:00+02:00 web1 cron: job done"]), Some("syslog".to_string()));
+        let mut formats = builtin_formats();
+        formats.retain(|name, _| name == "iso8601");
+        let lines = vec!["2020-09-01T12:00:00+02:00 web1 cron: job done".to_string()];
+        assert_eq!(detect_format(&lines, formats).map(|it| it.name), Some("iso8601".to_string()));
+    }
+
+    #[test]
+    fn format_rules() {
+        let rule = FormatRule::parse("nginx/*.log=syslog").unwrap();
+        assert!(rule.matches(Path::new("/var/log/nginx/access.log")));
+        assert!(!rule.matches(Path::new("/var/log/apache/access.log")));
+        assert_eq!(FormatRule::parse("nginx/*.log").err(), Some("format rule `nginx/*.log` is not of the form glob=format".to_string()));
+        assert!(FormatRule::parse("nginx/*.log=nosuchformat").is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use std::ops::Range;
+
+use termcolor::{Color, ColorSpec, WriteColor};
+
+pub struct Styles {
+    pub source: ColorSpec,
+    pub timestamp: ColorSpec,
+    pub matched: ColorSpec,
+    pub separator: ColorSpec,
+}
+
+impl Default for Styles {
+    /// Same colours as grep uses for file names, line numbers, matches and separators.
+    fn default() -> Self {
+        let spec = |color: Color, bold: bool| {
+            let mut it = ColorSpec::new();
+            it.set_fg(Some(color)).set_bold(bold);
+            it
+        };
+        Styles {
+            source: spec(Color::Magenta, false),
+            timestamp: spec(Color::Green, false),
+            matched: spec(Color::Red, true),
+            separator: spec(Color::Cyan, false),
+        }
+    }
+}
+
+/// Resolves the `--color` option: `always`, `never`, or `auto`, which only colours output that goes to a terminal.
+pub fn use_color(choice: &str, writing_to_terminal: bool) -> bool {
+    match choice {
+        "always" => true,
+        "never" => false,
+        _ => writing_to_terminal && std::env::var_os("NO_COLOR").is_none(),
+    }
+}
+
+/// Writes `text` colouring each of the `regions`.  Where regions overlap, the one that comes later wins.
+pub fn write_highlighted(output: &mut dyn WriteColor, text: &str, regions: &[(Range<usize>, &ColorSpec)]) -> std::io::Result<()> {
+    if regions.is_empty() || !output.supports_color() {
+        return output.write_all(text.as_bytes());
+    }
+
+    let mut boundaries = regions.iter()
+        .flat_map(|(range, _)| vec![range.start, range.end])
+        .filter(|it| *it <= text.len())
+        .chain(vec![0, text.len()])
+        .collect::<Vec<usize>>();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let style_at = |start: usize, end: usize| regions.iter().rev()
+        .find(|(range, _)| range.start <= start && end <= range.end)
+        .map(|it| it.1);
+    let mut segments = boundaries.windows(2).map(|it| (it[0], it[1], style_at(it[0], it[1]))).peekable();
+    while let Some((start, mut end, style)) = segments.next() {
+        while let Some(&(_, next_end, next_style)) = segments.peek() {
+            match (style, next_style) {
+                (Some(a), Some(b)) if std::ptr::eq(a, b) => {},
+                (None, None) => {},
+                _ => break,
+            }
+            end = next_end;
+            segments.next();
+        }
+        match style {
+            Some(spec) => {
+                output.set_color(spec)?;
+                output.write_all(&text.as_bytes()[start..end])?;
+                output.reset()?;
+            },
+            None => output.write_all(&text.as_bytes()[start..end])?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use termcolor::Buffer;
+
+    use super::*;
+
+    fn highlighted(text: &str, regions: &[(Range<usize>, &ColorSpec)]) -> Vec<u8> {
+        let mut buffer = Buffer::ansi();
+        write_highlighted(&mut buffer, text, regions).unwrap();
+        buffer.into_inner()
+    }
+
+    /// The `parts` of the text written one after the other, each in its colour, if any.
+    fn written(parts: &[(&[u8], Option<&ColorSpec>)]) -> Vec<u8> {
+        let mut buffer = Buffer::ansi();
+        for (text, spec) in parts {
+            match spec {
+                Some(spec) => {
+                    buffer.set_color(spec).unwrap();
+                    buffer.write_all(text).unwrap();
+                    buffer.reset().unwrap();
+                },
+                None => buffer.write_all(text).unwrap(),
+            }
+        }
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn later_regions_win_where_they_overlap() {
+        let styles = Styles::default();
+        let (red, green) = (&styles.matched, &styles.timestamp);
+        assert_eq!(
+            highlighted("abcdefg", &[(0..4, red), (2..6, green)]),
+            written(&[(b"ab", Some(red)), (b"cdef", Some(green)), (b"g", None)]),
+        );
+        assert_eq!(
+            highlighted("abcdefg", &[(1..6, green), (2..3, red)]),
+            written(&[(b"a", None), (b"b", Some(green)), (b"c", Some(red)), (b"def", Some(green)), (b"g", None)]),
+        );
+    }
+
+    #[test]
+    fn adjacent_regions_of_the_same_style_are_written_as_one() {
+        let styles = Styles::default();
+        let (red, green) = (&styles.matched, &styles.timestamp);
+        assert_eq!(highlighted("abcdef", &[(0..2, red), (2..4, red)]), written(&[(b"abcd", Some(red)), (b"ef", None)]));
+        assert_eq!(highlighted("abcdef", &[(0..2, red), (2..4, green)]), written(&[(b"ab", Some(red)), (b"cd", Some(green)), (b"ef", None)]));
+    }
+
+    #[test]
+    fn multi_byte_characters_are_written_whole() {
+        let styles = Styles::default();
+        let red = &styles.matched;
+        // `é` takes bytes 1..3, and `→` bytes 4..7.
+        assert_eq!(
+            highlighted("héx→y", &[(1..3, red), (4..7, red)]),
+            written(&[("h".as_bytes(), None), ("é".as_bytes(), Some(red)), (b"x", None), ("→".as_bytes(), Some(red)), (b"y", None)]),
+        );
+        let mut plain = Buffer::no_color();
+        write_highlighted(&mut plain, "héx→y", &[(1..3, red)]).unwrap();
+        assert_eq!(plain.into_inner(), "héx→y".as_bytes());
+    }
+}
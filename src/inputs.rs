@@ -0,0 +1,576 @@
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::errors::ReadErrors;
+
+pub const DEFAULT_INCLUDE_GLOB: &str = r"*.{log,log.[0-9]*,log-[0-9]*,log.gz,log.zst,log.bz2,log.*.gz,log.*.zst,log.*.bz2}";
+
+#[derive(Clone)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Input {
+    pub fn open(&self) -> std::io::Result<Box<dyn BufRead>> {
+        match self {
+            Input::Stdin => decompress(BufReader::new(std::io::stdin())),
+            Input::File(path) => open_file(path),
+        }
+    }
+
+    /// The path as is, or `(standard input)`.
+    pub fn name(&self) -> String {
+        match self {
+            Input::Stdin => "(standard input)".to_string(),
+            Input::File(path) => path.to_string_lossy().to_string(),
+        }
+    }
+
+    /// Whether the file can be opened, without keeping it open or decompressing it.
+    pub fn check(&self) -> std::io::Result<()> {
+        match self {
+            Input::Stdin => Ok(()),
+            Input::File(path) => std::fs::File::open(path).map(|_| ())
+                .map_err(|e| std::io::Error::new(e.kind(), format!("opening file {}: {}", path.to_string_lossy(), e))),
+        }
+    }
+
+    /// Reads lines as they are appended, see `follow::follow_file`.
+    pub fn follow(&self, encoding: Encoding) -> std::sync::mpsc::Receiver<String> {
+        match self {
+            Input::Stdin => crate::follow::follow_stdin(encoding),
+            Input::File(path) => crate::follow::follow_file(path.clone(), encoding),
+        }
+    }
+}
+
+pub fn open_file(path: &Path) -> std::io::Result<Box<dyn BufRead>> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("opening file {}: {}", path.to_string_lossy(), e)))?;
+    decompress(BufReader::new(file))
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(GZIP_MAGIC) || data.starts_with(ZSTD_MAGIC) || data.starts_with(BZIP2_MAGIC)
+}
+
+/// Looks at the magic bytes and, for gzip, zstd and bzip2 streams, decompresses on the fly.
+/// Anything else is read as is.
+pub fn decompress<R: BufRead + 'static>(mut reader: R) -> std::io::Result<Box<dyn BufRead>> {
+    if reader.fill_buf()?.len() >= ZSTD_MAGIC.len() {
+        return decompress_by_magic(reader);
+    }
+    // A pipe may hand out fewer bytes at a time than there are in the magic.
+    let mut magic = Vec::new();
+    (&mut reader).take(ZSTD_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    decompress_by_magic(std::io::Cursor::new(magic).chain(reader))
+}
+
+fn decompress_by_magic<R: BufRead + 'static>(mut reader: R) -> std::io::Result<Box<dyn BufRead>> {
+    let magic = reader.fill_buf()?;
+    Ok(if magic.starts_with(GZIP_MAGIC) {
+        Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)))
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?))
+    } else if magic.starts_with(BZIP2_MAGIC) {
+        Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader)))
+    } else {
+        Box::new(reader)
+    })
+}
+
+pub const ENCODINGS: &[&str] = &["utf-8", "latin1", "utf-16le"];
+
+pub const ENCODING_HELP: &str = "Encoding of the input. Invalid UTF-8 is replaced with U+FFFD rather than skipped.";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// Invalid sequences are replaced with U+FFFD.
+    Utf8,
+    /// ISO-8859-1, every byte is the code point of the same value.
+    Latin1,
+    Utf16Le,
+}
+
+impl Encoding {
+    /// Takes one of `ENCODINGS`.
+    pub fn from_name(name: &str) -> Result<Encoding, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "latin1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            _ => Err(format!("unsupported encoding `{}`, expected one of {}", name, ENCODINGS.join(", "))),
+        }
+    }
+
+    /// Whether lines are separated by a `\n` byte, so that the input can be split into lines before decoding.
+    pub fn is_ascii_compatible(&self) -> bool {
+        *self != Encoding::Utf16Le
+    }
+
+    /// Decodes text in an ASCII-compatible encoding.
+    pub fn decode(&self, line: &[u8]) -> String {
+        self.decode_borrowed(line).into_owned()
+    }
+
+    /// Like `decode`, without copying text that is the same decoded.
+    pub fn decode_borrowed<'a>(&self, line: &'a [u8]) -> std::borrow::Cow<'a, str> {
+        match self {
+            Encoding::Latin1 if !line.is_ascii() => line.iter().map(|it| *it as char).collect::<String>().into(),
+            _ => String::from_utf8_lossy(line),
+        }
+    }
+}
+
+/// Reads lines, without line terminators, decoding them from `encoding`.  Never fails on invalid input,
+/// so no line is ever dropped.
+pub fn lines(reader: Box<dyn BufRead>, encoding: Encoding) -> Box<dyn Iterator<Item = String>> {
+    lines_until_error(reader, encoding, |e| eprintln!("ERROR: reading input: {}", e))
+}
+
+/// Like `lines`, handing a read error to `errors` along with the name of the input.
+pub fn checked_lines(reader: Box<dyn BufRead>, encoding: Encoding, input: String, errors: ReadErrors) -> Box<dyn Iterator<Item = String>> {
+    lines_until_error(reader, encoding, move |e| {
+        let e = std::io::Error::new(e.kind(), format!("reading {}: {}", input, e));
+        errors.push(input, e)
+    })
+}
+
+/// The lines up to the first read error, which goes to `on_error`.  A corrupt compressed stream may fail the same
+/// way on every read, so there's no reading past it.
+fn lines_until_error<F>(reader: Box<dyn BufRead>, encoding: Encoding, on_error: F) -> Box<dyn Iterator<Item = String>>
+where
+    F: FnOnce(std::io::Error) + 'static
+{
+    use bstr::io::BufReadExt;
+    let reader: Box<dyn BufRead> = match encoding {
+        Encoding::Utf16Le => Box::new(BufReader::new(
+            encoding_rs_io::DecodeReaderBytesBuilder::new()
+                .encoding(Some(encoding_rs::UTF_16LE))
+                .build(reader)
+        )),
+        _ => reader,
+    };
+    let encoding = match encoding {
+        Encoding::Utf16Le => Encoding::Utf8,
+        it => it,
+    };
+    let mut on_error = Some(on_error);
+    Box::new(reader.byte_lines().map_while(move |it| match it {
+        Err(e) => {
+            if let Some(on_error) = on_error.take() {
+                on_error(e);
+            }
+            None
+        },
+        Ok(line) => Some(encoding.decode(&line)),
+    }))
+}
+
+/// The lines of `parts`, the first of which is already opened as `first`, opening each of the others only once
+/// the one before is read, so that a long chain of rotated files holds a single file and decompressor at a time.
+/// A file that fails to be opened or read by then goes to `errors` and is left out from there on, see `checked_lines`.
+pub fn chained_lines(first: Box<dyn BufRead>, parts: Vec<Input>, encoding: Encoding, errors: ReadErrors) -> Box<dyn Iterator<Item = String>> {
+    let first = checked_lines(first, encoding, parts[0].name(), errors.clone());
+    Box::new(first.chain(parts.into_iter().skip(1).flat_map(move |it| -> Box<dyn Iterator<Item = String>> {
+        match it.open() {
+            Ok(reader) => checked_lines(reader, encoding, it.name(), errors.clone()),
+            Err(e) => {
+                errors.push(it.name(), e);
+                Box::new(std::iter::empty())
+            },
+        }
+    })))
+}
+
+/// All the values of a glob option, matching when any of them does.
+pub fn parse_glob(cli: &clap::ArgMatches, name: &str) -> Result<Option<globset::GlobSet>, globset::Error> {
+    let globs = match cli.values_of(name) {
+        Some(it) => it,
+        None => return Ok(None),
+    };
+    let mut builder = globset::GlobSetBuilder::new();
+    for it in globs {
+        builder.add(globset::Glob::new(it)?);
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// Turns command line paths into inputs: `-` is stdin, `@list.txt` is the paths in that file, one per line,
+/// a file is taken as is, and a directory is walked recursively for the files that match `include` and don't match
+/// `exclude`, in file name order.
+pub fn expand_inputs<'a, P>(
+    paths: P,
+    include: Option<&globset::GlobSet>,
+    exclude: Option<&globset::GlobSet>,
+) -> std::io::Result<Vec<Input>>
+where
+    P: IntoIterator<Item = &'a str>,
+{
+    let mut inputs = Vec::new();
+    for path in paths {
+        match path.strip_prefix('@') {
+            Some(list) => {
+                let listed = std::fs::read_to_string(list)
+                    .map_err(|e| std::io::Error::new(e.kind(), format!("reading file list {}: {}", list, e)))?;
+                listed.lines()
+                    .map(|it| it.trim())
+                    .filter(|it| !it.is_empty())
+                    .for_each(|it| expand_path(it, include, exclude, &mut inputs));
+            },
+            None => expand_path(path, include, exclude, &mut inputs),
+        }
+    }
+    Ok(inputs)
+}
+
+fn expand_path(path: &str, include: Option<&globset::GlobSet>, exclude: Option<&globset::GlobSet>, inputs: &mut Vec<Input>) {
+    if path == "-" {
+        inputs.push(Input::Stdin);
+        return;
+    }
+    if !Path::new(path).is_dir() {
+        inputs.push(Input::File(PathBuf::from(path)));
+        return;
+    }
+    walkdir::WalkDir::new(path)
+        .min_depth(1)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_map(|it| match it {
+            Err(e) => { eprintln!("ERROR: {}", e); None },
+            Ok(dirent) => Some(dirent),
+        })
+        .filter(|dirent| dirent.file_type().is_file())
+        .filter(|dirent| include.is_none_or(|it| it.is_match(dirent.path())))
+        .filter(|dirent| !exclude.is_some_and(|it| it.is_match(dirent.path())))
+        .for_each(|dirent| inputs.push(Input::File(dirent.into_path())));
+}
+
+/// The source indicator prepended to log entries: the path as is, or truncated to its last directory and file name.
+pub fn source_label(path: &Path, truncate_last_dir: bool) -> String {
+    match truncate_last_dir {
+        false => path.to_string_lossy().to_string(),
+        true => format!("{}{}{}",
+            match path.parent().and_then(|it| it.file_name()) {
+                Some(it) => it.to_string_lossy(),
+                None => ".".into(),
+            },
+            std::path::MAIN_SEPARATOR,
+            path.file_name().map_or("".into(), |it| it.to_string_lossy())
+        ),
+    }
+}
+
+pub const LABEL_PLACEHOLDERS: &[&str] = &["path", "dir", "file", "stem", "host"];
+
+lazy_static::lazy_static! {
+    static ref PLACEHOLDER_REGEX: regex::Regex = regex::Regex::new(r"\{(\w+)\}").unwrap();
+}
+
+/// A source label template like `{host} {stem}`.  `{path}` is the path as is, `{dir}` the name of the directory
+/// of the file, `{file}` its name, and `{stem}` its name up to the first dot.  Every named group of the optional
+/// regex, matched against the path, is a placeholder too.  `{host}` is the `host` group when there is one,
+/// or else `{dir}`.
+pub struct LabelTemplate {
+    template: String,
+    regex: Option<regex::Regex>,
+}
+
+impl LabelTemplate {
+    pub fn new(template: &str, regex: Option<&str>) -> Result<LabelTemplate, String> {
+        let regex = match regex {
+            Some(it) => Some(regex::Regex::new(it).map_err(|e| format!("invalid source label regex: {}", e))?),
+            None => None,
+        };
+        for captured in PLACEHOLDER_REGEX.captures_iter(template) {
+            let name = &captured[1];
+            if !LABEL_PLACEHOLDERS.contains(&name) && !regex.iter().flat_map(|it| it.capture_names()).any(|it| it == Some(name)) {
+                return Err(format!(
+                    "unknown placeholder `{{{}}}` in source label `{}`, expected one of {} or a named group of the regex",
+                    name,
+                    template,
+                    LABEL_PLACEHOLDERS.iter().map(|it| format!("{{{}}}", it)).collect::<Vec<_>>().join(", "),
+                ));
+            }
+        }
+        Ok(LabelTemplate { template: template.to_string(), regex })
+    }
+
+    pub fn render(&self, path: &Path) -> String {
+        let text = path.to_string_lossy();
+        let captured = self.regex.as_ref().and_then(|it| it.captures(&text));
+        let file = path.file_name().map_or("".into(), |it| it.to_string_lossy());
+        let dir = path.parent().and_then(|it| it.file_name()).map_or("".into(), |it| it.to_string_lossy());
+        PLACEHOLDER_REGEX.replace_all(&self.template, |placeholder: &regex::Captures| {
+            let name = &placeholder[1];
+            if let Some(it) = captured.as_ref().and_then(|it| it.name(name)) {
+                return it.as_str().to_string();
+            }
+            match name {
+                "path" => text.to_string(),
+                "dir" | "host" => dir.to_string(),
+                "file" => file.to_string(),
+                "stem" => file.split('.').next().unwrap_or_default().to_string(),
+                _ => "".to_string(),
+            }
+        }).to_string()
+    }
+}
+
+/// Pads labels with spaces to `width` characters, or to the longest one for `auto`.  Longer labels are cut
+/// to `width`, so that the entries line up all the same.
+pub fn pad_labels(labels: &mut [String], width: &str) -> Result<(), String> {
+    let width = match width {
+        "auto" => labels.iter().map(|it| it.chars().count()).max().unwrap_or(0),
+        _ => width.parse::<usize>().map_err(|e| format!("label width `{}` is neither a number nor `auto`: {}", width, e))?,
+    };
+    for label in labels {
+        if let Some((end, _)) = label.char_indices().nth(width) {
+            label.truncate(end);
+        }
+        let padding = width.saturating_sub(label.chars().count());
+        label.extend(std::iter::repeat_n(' ', padding));
+    }
+    Ok(())
+}
+
+/// The glob of a per-source option like `--source-skew host3=+2.4s`.  It's matched against the whole path and
+/// against every run of its components, so that `host3` matches every file under a `host3` directory.
+pub struct SourceGlob(globset::GlobMatcher);
+
+impl SourceGlob {
+    pub fn matches(&self, path: &Path) -> bool {
+        let components = path.components().collect::<Vec<_>>();
+        self.0.is_match(path) || (0..components.len()).any(|start| {
+            (start + 1..=components.len()).any(|end| self.0.is_match(components[start..end].iter().collect::<PathBuf>()))
+        })
+    }
+}
+
+/// Splits a per-source rule like `host3=+2.4s` into its glob and its value.
+pub fn parse_source_rule(rule: &str) -> Result<(SourceGlob, &str), String> {
+    let (glob, value) = parse_glob_rule(rule, "glob=value")?;
+    Ok((SourceGlob(glob), value))
+}
+
+/// Splits a rule like `host3=+2.4s` or `nginx/*.log=nginx` at its last `=` into its glob and its value.
+/// `form` is what errors say the rule should look like, e.g. `glob=format`.
+pub fn parse_glob_rule<'a>(rule: &'a str, form: &str) -> Result<(globset::GlobMatcher, &'a str), String> {
+    let (glob, value) = match rule.rfind('=') {
+        Some(it) => (&rule[..it], &rule[it + 1..]),
+        None => return Err(format!("`{}` is not of the form {}", rule, form)),
+    };
+    let glob = globset::Glob::new(glob).map_err(|e| format!("`{}`: {}", rule, e))?;
+    Ok((glob.compile_matcher(), value))
+}
+
+lazy_static::lazy_static! {
+    static ref ROTATED_REGEX: regex::Regex = regex::Regex::new(
+        r"^(?P<base>.+?)(?:[.-](?P<date>\d{8}|\d{4}-\d{2}-\d{2})|\.(?P<number>\d+))?(?P<compression>\.(?:gz|zst|bz2))?$"
+    ).unwrap();
+}
+
+/// Where a file comes in its rotation family, oldest first.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Rotation {
+    Date(String),
+    /// Reversed, higher numbers being older.
+    Number(std::cmp::Reverse<u64>),
+    Compressed,
+    Current,
+}
+
+/// The path shared by a rotation family, e.g. `app.log` for `app.log.2.gz`, `app.log.20200901` or
+/// `app.log-20200901`, and the file's place in it.  Numbers only count after a dot, so that `node-1.log` and
+/// `node-2.log` stay apart.
+fn rotation(path: &Path) -> (PathBuf, Rotation) {
+    let name = path.file_name().map_or("".into(), |it| it.to_string_lossy());
+    let captured = match ROTATED_REGEX.captures(&name) {
+        Some(it) => it,
+        None => return (path.to_path_buf(), Rotation::Current),
+    };
+    let rotation = match (captured.name("date"), captured.name("number"), captured.name("compression")) {
+        (Some(date), _, _) => Rotation::Date(date.as_str().replace('-', "")),
+        (_, Some(number), _) => match number.as_str().parse::<u64>() {
+            Ok(it) => Rotation::Number(std::cmp::Reverse(it)),
+            Err(_) => return (path.to_path_buf(), Rotation::Current),
+        },
+        (_, _, Some(_)) => Rotation::Compressed,
+        _ => Rotation::Current,
+    };
+    (path.with_file_name(&captured["base"]), rotation)
+}
+
+/// Groups rotated files like `app.log.2.gz`, `app.log.1` and `app.log` into families, each to be read oldest
+/// first as a single source.  A family is named after its current file, even when that one isn't among the
+/// inputs, and comes where its first file did.  Stdin stays on its own.
+pub fn group_rotated(inputs: Vec<Input>) -> Vec<(Input, Vec<Input>)> {
+    let mut families: Vec<(Input, Vec<(Rotation, Input)>)> = Vec::new();
+    for input in inputs {
+        let path = match &input {
+            Input::File(it) => it.clone(),
+            Input::Stdin => {
+                families.push((Input::Stdin, vec![(Rotation::Current, input)]));
+                continue;
+            },
+        };
+        let (family, rotation) = rotation(&path);
+        match families.iter_mut().find(|it| matches!(&it.0, Input::File(it) if *it == family)) {
+            Some(it) => it.1.push((rotation, input)),
+            None => families.push((Input::File(family), vec![(rotation, input)])),
+        }
+    }
+    families.into_iter()
+        .map(|(family, mut files)| {
+            files.sort_by(|a, b| a.0.cmp(&b.0));
+            (family, files.into_iter().map(|it| it.1).collect())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use super::*;
+
+    /// Decompresses `data` as it comes out of a reader that hands out a byte at a time, like a slow pipe.
+    fn decompressed(data: Vec<u8>) -> String {
+        let mut text = String::new();
+        decompress(BufReader::with_capacity(1, Cursor::new(data))).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn decompress_detects_compression_from_a_byte_at_a_time() {
+        let text = "2020-09-01 00:00:00,000 INFO started\n";
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(text.as_bytes()).unwrap();
+        let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bzip2.write_all(text.as_bytes()).unwrap();
+        assert_eq!(decompressed(gzip.finish().unwrap()), text);
+        assert_eq!(decompressed(zstd::encode_all(text.as_bytes(), 0).unwrap()), text);
+        assert_eq!(decompressed(bzip2.finish().unwrap()), text);
+        assert_eq!(decompressed(text.as_bytes().to_vec()), text);
+    }
+
+    #[test]
+    fn lines_stop_at_the_first_error_of_a_truncated_stream() {
+        let text = "2020-09-01 00:00:00,000 INFO started\n2020-09-01 00:00:01,000 INFO running\n";
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(text.as_bytes()).unwrap();
+        let mut data = gzip.finish().unwrap();
+        data.truncate(data.len() - 4);
+        let errors = ReadErrors::default();
+        let reader = decompress(BufReader::new(Cursor::new(data))).unwrap();
+        let read = checked_lines(reader, Encoding::Utf8, "app.log.gz".to_string(), errors.clone()).collect::<Vec<_>>();
+        assert!(read.len() <= 2);
+        let errors = errors.take();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "app.log.gz");
+        assert!(errors[0].1.to_string().starts_with("reading app.log.gz: "));
+    }
+
+    #[test]
+    fn decompress_passes_short_input_through() {
+        assert_eq!(decompressed(Vec::new()), "");
+        assert_eq!(decompressed(b"ab".to_vec()), "ab");
+        assert_eq!(decompressed(b"BZ".to_vec()), "BZ");
+    }
+
+    #[test]
+    fn source_globs() {
+        let glob = |rule: &str| parse_source_rule(rule).unwrap().0;
+        let path = Path::new("/var/log/host3/nginx/access.log");
+        assert!(glob("/var/log/**/*.log=+2s").matches(path));
+        assert!(glob("nginx/*.log=+2s").matches(path));
+        assert!(glob("host3=+2s").matches(path));
+        assert!(glob("host3/nginx=+2s").matches(path));
+        assert!(!glob("host=+2s").matches(path));
+        assert!(parse_source_rule("host3").is_err());
+    }
+
+    fn grouped(paths: &[&str]) -> Vec<(String, Vec<String>)> {
+        let inputs = paths.iter()
+            .map(|it| match *it {
+                "-" => Input::Stdin,
+                _ => Input::File(PathBuf::from(it)),
+            })
+            .collect();
+        group_rotated(inputs).into_iter()
+            .map(|(family, files)| (family.name(), files.iter().map(|it| it.name()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn rotated_files_are_read_oldest_first() {
+        assert_eq!(grouped(&["logs/app.log", "logs/app.log.1", "logs/other.log", "logs/app.log.10.gz", "logs/app.log.2.gz"]), vec![
+            ("logs/app.log".to_string(), vec![
+                "logs/app.log.10.gz".to_string(), "logs/app.log.2.gz".to_string(), "logs/app.log.1".to_string(), "logs/app.log".to_string(),
+            ]),
+            ("logs/other.log".to_string(), vec!["logs/other.log".to_string()]),
+        ]);
+        assert_eq!(grouped(&["app.log.20200902", "app.log", "app.log.2020-09-01.gz", "app.log.gz"]), vec![
+            ("app.log".to_string(), vec![
+                "app.log.2020-09-01.gz".to_string(), "app.log.20200902".to_string(), "app.log.gz".to_string(), "app.log".to_string(),
+            ]),
+        ]);
+    }
+
+    #[test]
+    fn dashed_dates_are_rotations_but_dashed_numbers_are_not() {
+        assert_eq!(grouped(&["app.log-20200901", "node-1.log", "app.log", "node-2.log", "app.log-2020-09-02.gz"]), vec![
+            ("app.log".to_string(), vec![
+                "app.log-20200901".to_string(), "app.log-2020-09-02.gz".to_string(), "app.log".to_string(),
+            ]),
+            ("node-1.log".to_string(), vec!["node-1.log".to_string()]),
+            ("node-2.log".to_string(), vec!["node-2.log".to_string()]),
+        ]);
+        assert_eq!(grouped(&["node-1", "node-2", "node-1.1"]), vec![
+            ("node-1".to_string(), vec!["node-1.1".to_string(), "node-1".to_string()]),
+            ("node-2".to_string(), vec!["node-2".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn families_are_named_after_their_current_file() {
+        assert_eq!(grouped(&["-", "web/access.log.1", "db/access.log.1", "access-2.log"]), vec![
+            ("(standard input)".to_string(), vec!["(standard input)".to_string()]),
+            ("web/access.log".to_string(), vec!["web/access.log.1".to_string()]),
+            ("db/access.log".to_string(), vec!["db/access.log.1".to_string()]),
+            ("access-2.log".to_string(), vec!["access-2.log".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn labels_fill_in_placeholders() {
+        let path = Path::new("/var/log/web-03/nginx/access.log.1");
+        let render = |template: &str, regex: Option<&str>| LabelTemplate::new(template, regex).unwrap().render(path);
+        assert_eq!(render("{host} {stem}", None), "nginx access");
+        assert_eq!(render("{dir}/{file}", None), "nginx/access.log.1");
+        assert_eq!(render("[{path}]", None), "[/var/log/web-03/nginx/access.log.1]");
+        assert_eq!(render("{host}:{stem}", Some(r"/(?P<host>web-\d+)/")), "web-03:access");
+        assert_eq!(render("{host} {n}", Some(r"web-(?P<n>\d+)")), "nginx 03");
+        assert_eq!(render("{n}", Some(r"db-(?P<n>\d+)")), "");
+        assert!(LabelTemplate::new("{hostname}", None).is_err());
+        assert!(LabelTemplate::new("{n}", Some("(")).is_err());
+    }
+
+    #[test]
+    fn labels_are_padded() {
+        let mut labels = vec!["web".to_string(), "db-1".to_string(), "ünï".to_string()];
+        pad_labels(&mut labels, "auto").unwrap();
+        assert_eq!(labels, vec!["web ", "db-1", "ünï "]);
+        pad_labels(&mut labels, "6").unwrap();
+        assert_eq!(labels, vec!["web   ", "db-1  ", "ünï   "]);
+        pad_labels(&mut labels, "2").unwrap();
+        assert_eq!(labels, vec!["we", "db", "ün"]);
+        assert!(pad_labels(&mut labels, "wide").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! NDJSON logs, i.e. one JSON object per line, as an entry source alongside the regex-based `LogEntryIterator`.
+//!
+//! Every line is a log entry.  The timestamp is taken from a key path like `@timestamp` or `meta.time`,
+//! and all the values become fields, nested ones under dotted names like `http.status`.
+
+use std::collections::BTreeMap;
+use std::io::BufRead;
+
+use chrono::TimeZone;
+use logentry::entry::Zdt;
+use serde_json::Value;
+
+use crate::matchers::Fields;
+use crate::record::Record;
+
+/// Key paths tried in this order when no timestamp key is given.
+pub const DEFAULT_TIMESTAMP_KEYS: &[&str] = &["timestamp", "@timestamp", "time", "ts"];
+
+/// Whether the input looks like NDJSON, i.e. begins with `{`.  Doesn't consume anything.
+pub fn is_ndjson(reader: &mut dyn BufRead) -> std::io::Result<bool> {
+    Ok(is_ndjson_start(reader.fill_buf()?))
+}
+
+pub fn is_ndjson_start(data: &[u8]) -> bool {
+    let data = data.strip_prefix(b"\xef\xbb\xbf".as_ref()).unwrap_or(data);
+    data.iter().find(|it| !it.is_ascii_whitespace()) == Some(&b'{')
+}
+
+/// Values of a JSON object by dotted key path.  Strings are taken as is, other values as JSON.
+pub struct JsonFields(pub BTreeMap<String, String>);
+
+impl Fields for JsonFields {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(|it| it.as_str())
+    }
+}
+
+/// None when the value isn't a JSON object.
+pub fn json_fields(value: &Value) -> Option<JsonFields> {
+    match value {
+        Value::Object(_) => {
+            let mut fields = BTreeMap::new();
+            flatten("", value, &mut fields);
+            Some(JsonFields(fields))
+        },
+        _ => None,
+    }
+}
+
+fn flatten(path: &str, value: &Value, fields: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(it) => {
+            for (key, value) in it {
+                match path.is_empty() {
+                    true => flatten(key, value, fields),
+                    false => flatten(&format!("{}.{}", path, key), value, fields),
+                }
+            }
+        },
+        Value::String(it) => { fields.insert(path.to_string(), it.clone()); },
+        other => { fields.insert(path.to_string(), other.to_string()); },
+    }
+}
+
+/// Looks up a dotted key path, e.g. `meta.time`.
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if let Some(it) = value.get(path) {
+        return Some(it);
+    }
+    path.split('.').try_fold(value, |value, key| value.get(key))
+}
+
+/// RFC 3339 strings, strings in `timestamp_pattern`, and numbers of seconds, or milliseconds for
+/// the larger ones, since the epoch.
+pub fn parse_json_timestamp(value: &Value, timestamp_pattern: Option<&str>) -> Option<Zdt> {
+    match value {
+        Value::String(text) => match chrono::DateTime::parse_from_rfc3339(text) {
+            Ok(it) => Some(it.with_timezone(&chrono::Utc)),
+            Err(_) => crate::timerange::parse_timestamp_text(text, timestamp_pattern?),
+        },
+        Value::Number(number) => {
+            let value = number.as_f64()?;
+            let millis = match value.abs() >= 1e11 {
+                true => value,
+                false => value * 1000.0,
+            };
+            chrono::Utc.timestamp_millis_opt(millis as i64).single()
+        },
+        _ => None,
+    }
+}
+
+/// Log entries of an NDJSON input.  Lines that aren't JSON become entries without a timestamp, empty lines are skipped.
+pub struct JsonEntries {
+    lines: Box<dyn Iterator<Item = String>>,
+    timestamp_keys: Vec<String>,
+    timestamp_pattern: Option<String>,
+}
+
+impl JsonEntries {
+    /// `timestamp_keys` are tried in order, see `DEFAULT_TIMESTAMP_KEYS`.  `timestamp_pattern` is for timestamps
+    /// that aren't RFC 3339.
+    pub fn new(lines: Box<dyn Iterator<Item = String>>, timestamp_keys: Vec<String>, timestamp_pattern: Option<String>) -> Self {
+        JsonEntries { lines, timestamp_keys, timestamp_pattern }
+    }
+
+    fn timestamp(&self, value: &Value) -> Option<Zdt> {
+        self.timestamp_keys.iter()
+            .find_map(|it| lookup(value, it))
+            .and_then(|it| parse_json_timestamp(it, self.timestamp_pattern.as_deref()))
+    }
+}
+
+impl Iterator for JsonEntries {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        loop {
+            let text = self.lines.next()?;
+            if text.trim().is_empty() {
+                continue;
+            }
+            let json = serde_json::from_str::<Value>(&text).unwrap_or(Value::Null);
+            let zdt = self.timestamp(&json);
+            return Some(Record { text, zdt, json: Some(json) });
+        }
+    }
+}
+
+/// The `--json-timestamp-key` option, or `DEFAULT_TIMESTAMP_KEYS`.
+pub fn timestamp_keys_from_cli(cli: &clap::ArgMatches, name: &str) -> Vec<String> {
+    match cli.value_of(name) {
+        Some(it) => vec![it.to_string()],
+        None => DEFAULT_TIMESTAMP_KEYS.iter().map(|it| it.to_string()).collect(),
+    }
+}
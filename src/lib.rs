@@ -1 +1,15 @@
 pub mod matchers;
+pub mod expression;
+pub mod timerange;
+pub mod context;
+pub mod highlight;
+pub mod inputs;
+pub mod record;
+pub mod follow;
+pub mod parallel;
+pub mod formats;
+pub mod json;
+pub mod merge;
+pub mod output;
+pub mod clock;
+pub mod errors;
@@ -1,3 +1,8 @@
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+
 use either::{Left, Right};
 
 fn unwrap_or_empty<L, I>(opt_iter: Option<L>) -> either::Either<L, std::iter::Empty<I>>
@@ -10,32 +15,550 @@ where
     }
 }
 
+/// Collects the verbatim and regex patterns given on the command line and in pattern files, see
+/// `read_pattern_file`.  All verbatim patterns are compiled into a single automaton and all regexes
+/// into a single `RegexSet`, so that an entry is scanned once per kind no matter how many patterns there are.
+/// Each pattern may override `defaults` with a prefix, see `PatternOptions::split_prefix`.
+pub fn parse_matchers(
+    cli: &clap::ArgMatches,
+    verbatim_name: &str,
+    regex_name: &str,
+    file_name: &str,
+    defaults: PatternOptions,
+) -> Result<Vec<Box<dyn Matcher>>, String> {
+    let mut scoped_patterns = BTreeMap::<Scope, Patterns>::new();
+    for it in unwrap_or_empty(cli.values_of(verbatim_name)) {
+        let (options, text) = defaults.split_prefix(it)?;
+        scoped_patterns.entry(options.scope).or_default().add_verbatim(text, options);
+    }
+    for it in unwrap_or_empty(cli.values_of(regex_name)) {
+        let (options, pattern) = defaults.split_prefix(it)?;
+        scoped_patterns.entry(options.scope).or_default().add_regex(pattern, options);
+    }
+    for path in unwrap_or_empty(cli.values_of(file_name)) {
+        read_pattern_file(Path::new(path), &mut scoped_patterns, defaults)?;
+    }
+
+    let mut matchers: Vec<Box<dyn Matcher>> = Vec::new();
+    for (scope, patterns) in scoped_patterns {
+        if !patterns.verbatims.is_empty() {
+            matchers.push(scoped(Box::new(VerbatimSetMatcher::new(&patterns.verbatims, false)?), scope));
+        }
+        if !patterns.ascii_case_insensitive_verbatims.is_empty() {
+            matchers.push(scoped(Box::new(VerbatimSetMatcher::new(&patterns.ascii_case_insensitive_verbatims, true)?), scope));
+        }
+        if !patterns.regexes.is_empty() {
+            matchers.push(scoped(Box::new(RegexSetMatcher::new(&patterns.regexes)?), scope));
+        }
+    }
+    Ok(matchers)
+}
+
+/// Patterns of one scope, sorted by how they can be matched the fastest.
+#[derive(Default)]
+struct Patterns {
+    verbatims: Vec<String>,
+    ascii_case_insensitive_verbatims: Vec<String>,
+    regexes: Vec<String>,
+}
+
+impl Patterns {
+    fn add_verbatim(&mut self, text: &str, options: PatternOptions) {
+        if options.whole_word || (options.ignore_case && !text.is_ascii()) {
+            self.regexes.push(options.verbatim_regex(text));
+        } else if options.ignore_case {
+            self.ascii_case_insensitive_verbatims.push(text.to_string());
+        } else {
+            self.verbatims.push(text.to_string());
+        }
+    }
+
+    fn add_regex(&mut self, pattern: &str, options: PatternOptions) {
+        self.regexes.push(options.regex(pattern));
+    }
+}
+
+/// One pattern per line, matched as is, or as a regex when enclosed in slashes, e.g. `/timeout \d+ms/`.
+/// Flag prefixes go before the slashes, e.g. `i:/timeout \d+ms/`.  Empty lines and lines starting with `#` are skipped.
+fn read_pattern_file(path: &Path, scoped_patterns: &mut BTreeMap<Scope, Patterns>, defaults: PatternOptions) -> Result<(), String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("reading pattern file {}: {}", path.to_string_lossy(), e))?;
+    for line in content.lines() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (options, line) = defaults.split_prefix(line)
+            .map_err(|e| format!("pattern file {}: {}", path.to_string_lossy(), e))?;
+        let patterns = scoped_patterns.entry(options.scope).or_default();
+        if line.len() >= 2 && line.starts_with('/') && line.ends_with('/') {
+            patterns.add_regex(&line[1..line.len() - 1], options);
+        } else {
+            patterns.add_verbatim(line, options);
+        }
+    }
+    Ok(())
+}
 
-pub fn parse_matchers(cli: &clap::ArgMatches, verbatim_name: &str, regex_name: &str) -> Vec<Box<dyn Matcher>> {
-    let verbatims = unwrap_or_empty(cli.values_of(verbatim_name))
-        .map(|it| VerbatimMatcher(it.to_string()))
-        .map(|it| Box::new(it) as Box<dyn Matcher>);
-    let regexes = unwrap_or_empty(cli.values_of(regex_name))
-        .map(|it| RegexMatcher(regex::Regex::new(it).unwrap()))
-        .map(|it| Box::new(it) as Box<dyn Matcher>);
-    verbatims.chain(regexes).collect::<Vec<Box<dyn Matcher>>>()
+/// How a pattern is matched.
+#[derive(Clone, Copy, Default)]
+pub struct PatternOptions {
+    pub ignore_case: bool,
+    pub whole_word: bool,
+    pub scope: Scope,
 }
 
-pub trait Matcher {
+/// Flags of a pattern prefix: `i` ignores case, `I` respects it, `w` matches whole words, `W` anywhere,
+/// `h`, `b` and `a` set the scope to the header, body, or all of the entry.  The scope ones only go in `(?...)`.
+const PREFIX_FLAGS: &str = "iIwWhba";
+
+/// The flags that may also go before a colon.  Only the case and word ones, since every other letter would turn
+/// more patterns like `a:b` into a prefix.
+const COLON_FLAGS: &str = "iIwW";
+
+impl PatternOptions {
+    /// Splits off a prefix like `(?i)`, `(?iw)` or `iw:` that overrides these options for one pattern.  Only one
+    /// or more of `PREFIX_FLAGS` in `(?...)`, or of `COLON_FLAGS` before a colon, make a prefix, which for regexes
+    /// means what the inline flags do anyway, anything else is part of the pattern.  An empty `:` prefix escapes
+    /// a pattern that looks like it has one, e.g. `:i:timeout` matches `i:timeout`.  Nothing but a prefix is an
+    /// error rather than a pattern that matches everything.
+    pub fn split_prefix<'a>(&self, pattern: &'a str) -> Result<(PatternOptions, &'a str), String> {
+        let is_flags = |flags: &str, allowed: &str| !flags.is_empty() && flags.chars().all(|it| allowed.contains(it));
+        let (flags, rest) = match pattern.strip_prefix("(?").and_then(|it| it.split_once(')')) {
+            Some((flags, rest)) if is_flags(flags, PREFIX_FLAGS) => (flags, rest),
+            _ => match pattern.split_once(':') {
+                Some((flags, rest)) if flags.is_empty() || is_flags(flags, COLON_FLAGS) => (flags, rest),
+                _ => return Ok((*self, pattern)),
+            },
+        };
+        if rest.is_empty() {
+            return Err(format!("pattern `{}` is empty after its prefix, a leading `:` takes the rest as is", pattern));
+        }
+        let mut options = *self;
+        for flag in flags.chars() {
+            match flag {
+                'i' => options.ignore_case = true,
+                'I' => options.ignore_case = false,
+                'w' => options.whole_word = true,
+                'W' => options.whole_word = false,
+                'h' => options.scope = Scope::Header,
+                'b' => options.scope = Scope::Body,
+                _ => options.scope = Scope::All,
+            }
+        }
+        Ok((options, rest))
+    }
+
+    /// Whether a verbatim pattern can be matched as is, regardless of the scope.
+    pub fn is_plain(&self) -> bool {
+        !self.ignore_case && !self.whole_word
+    }
+
+    /// The regex that matches what `pattern` does, with these options.
+    pub fn regex(&self, pattern: &str) -> String {
+        let pattern = match self.whole_word {
+            true => format!(r"\b(?:{})\b", pattern),
+            false => pattern.to_string(),
+        };
+        match self.ignore_case {
+            true => format!("(?i){}", pattern),
+            false => pattern,
+        }
+    }
+
+    /// The regex that matches `text` as is, with these options.  Word boundaries are only required
+    /// next to word characters, so that a whole-word `[ERROR]` still matches in `x [ERROR] y`.
+    pub fn verbatim_regex(&self, text: &str) -> String {
+        let is_word = |it: Option<char>| it.is_some_and(|it| it.is_alphanumeric() || it == '_');
+        let mut pattern = regex::escape(text);
+        if self.whole_word && is_word(text.chars().next()) {
+            pattern = format!(r"\b{}", pattern);
+        }
+        if self.whole_word && is_word(text.chars().last()) {
+            pattern.push_str(r"\b");
+        }
+        match self.ignore_case {
+            true => format!("(?i){}", pattern),
+            false => pattern,
+        }
+    }
+}
+
+pub fn parse_field_matchers(cli: &clap::ArgMatches, name: &str) -> Result<Vec<Box<dyn Matcher>>, String> {
+    unwrap_or_empty(cli.values_of(name))
+        .map(crate::expression::parse_field)
+        .collect()
+}
+
+pub trait Matcher: Send + Sync {
     fn matches<'a>(&self, s: &'a str) -> bool;
+
+    /// Like `matches`, but also gets to see the named fields of the log entry.
+    fn matches_entry<'a>(&self, s: &'a str, _fields: &dyn Fields) -> bool {
+        self.matches(s)
+    }
+
+    /// Byte ranges of `s` hit by this matcher, for highlighting.  May overlap.
+    fn spans<'a>(&self, _s: &'a str) -> Vec<Range<usize>> {
+        Vec::new()
+    }
 }
 
-pub struct VerbatimMatcher(String);
-pub struct RegexMatcher(regex::Regex);
+/// Named fields of a log entry, e.g. capture groups of the entry pattern.
+pub trait Fields {
+    fn field(&self, name: &str) -> Option<&str>;
+}
+
+impl<'t> Fields for regex::Captures<'t> {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.name(name).map(|it| it.as_str())
+    }
+}
+
+impl<T: Fields> Fields for Option<T> {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.as_ref().and_then(|it| it.field(name))
+    }
+}
+
+/// The capture groups of the entry pattern, only captured once a field is asked for, so that entries
+/// that are only matched as text don't pay for running the entry pattern again.
+pub struct LazyCaptures<'r, 't> {
+    regex: &'r regex::Regex,
+    text: &'t str,
+    captures: OnceCell<Option<regex::Captures<'t>>>,
+}
+
+impl<'r, 't> LazyCaptures<'r, 't> {
+    pub fn new(regex: &'r regex::Regex, text: &'t str) -> Self {
+        LazyCaptures { regex, text, captures: OnceCell::new() }
+    }
+}
+
+impl<'r, 't> Fields for LazyCaptures<'r, 't> {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.captures.get_or_init(|| self.regex.captures(self.text)).field(name)
+    }
+}
+
+pub struct VerbatimMatcher(pub String);
+pub struct RegexMatcher(pub regex::Regex);
+pub struct EqualsMatcher(pub String);
 
 impl Matcher for VerbatimMatcher {
     fn matches<'a>(&self, s: &'a str) -> bool {
         s.contains(self.0.as_str())
     }
+
+    fn spans<'a>(&self, s: &'a str) -> Vec<Range<usize>> {
+        match self.0.is_empty() {
+            true => Vec::new(),
+            false => s.match_indices(self.0.as_str()).map(|(start, it)| start..start + it.len()).collect(),
+        }
+    }
 }
 
 impl Matcher for RegexMatcher {
     fn matches<'a>(&self, s: &'a str) -> bool {
         self.0.is_match(s)
     }
+
+    fn spans<'a>(&self, s: &'a str) -> Vec<Range<usize>> {
+        self.0.find_iter(s).filter(|it| it.start() < it.end()).map(|it| it.start()..it.end()).collect()
+    }
+}
+
+impl Matcher for EqualsMatcher {
+    fn matches<'a>(&self, s: &'a str) -> bool {
+        s == self.0
+    }
+
+    fn spans<'a>(&self, s: &'a str) -> Vec<Range<usize>> {
+        match self.matches(s) && !s.is_empty() {
+            true => std::iter::once(0..s.len()).collect(),
+            false => Vec::new(),
+        }
+    }
+}
+
+/// Matches when any of the strings is contained in the text, looking for all of them in one pass.
+pub struct VerbatimSetMatcher(pub aho_corasick::AhoCorasick);
+
+impl VerbatimSetMatcher {
+    pub fn new(patterns: &[String], ascii_case_insensitive: bool) -> Result<Self, String> {
+        aho_corasick::AhoCorasick::builder()
+            .ascii_case_insensitive(ascii_case_insensitive)
+            .build(patterns)
+            .map(VerbatimSetMatcher)
+            .map_err(|e| format!("compiling verbatim patterns: {}", e))
+    }
+}
+
+impl Matcher for VerbatimSetMatcher {
+    fn matches<'a>(&self, s: &'a str) -> bool {
+        self.0.is_match(s)
+    }
+
+    fn spans<'a>(&self, s: &'a str) -> Vec<Range<usize>> {
+        self.0.find_overlapping_iter(s).filter(|it| it.start() < it.end()).map(|it| it.start()..it.end()).collect()
+    }
+}
+
+/// Matches when any of the regexes does.  The individual regexes are only run for highlighting,
+/// and only those that are known to match.
+pub struct RegexSetMatcher {
+    pub set: regex::RegexSet,
+    pub regexes: Vec<regex::Regex>,
+}
+
+impl RegexSetMatcher {
+    pub fn new(patterns: &[String]) -> Result<Self, String> {
+        let regexes = patterns.iter()
+            .map(|it| regex::Regex::new(it).map_err(|e| format!("invalid regex `{}`: {}", it, e)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let set = regex::RegexSet::new(patterns).map_err(|e| format!("compiling regexes: {}", e))?;
+        Ok(RegexSetMatcher { set, regexes })
+    }
+}
+
+impl Matcher for RegexSetMatcher {
+    fn matches<'a>(&self, s: &'a str) -> bool {
+        self.set.is_match(s)
+    }
+
+    fn spans<'a>(&self, s: &'a str) -> Vec<Range<usize>> {
+        self.set.matches(s).into_iter()
+            .flat_map(|index| self.regexes[index].find_iter(s))
+            .filter(|it| it.start() < it.end())
+            .map(|it| it.start()..it.end())
+            .collect()
+    }
+}
+
+pub const SCOPES: &[&str] = &["all", "header", "body"];
+
+/// The part of a log entry that a pattern is matched against.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum Scope {
+    #[default]
+    All,
+    /// The first line.
+    Header,
+    /// Everything after the first line, e.g. a stack trace.
+    Body,
+}
+
+impl Scope {
+    /// Takes one of `SCOPES`.
+    pub fn from_name(name: &str) -> Result<Scope, String> {
+        match name {
+            "all" => Ok(Scope::All),
+            "header" => Ok(Scope::Header),
+            "body" => Ok(Scope::Body),
+            _ => Err(format!("unsupported scope `{}`, expected one of {}", name, SCOPES.join(", "))),
+        }
+    }
+
+    /// The part of `text` in this scope, along with its offset in `text`.
+    pub fn part<'a>(&self, text: &'a str) -> (usize, &'a str) {
+        let header_end = text.find('\n');
+        match self {
+            Scope::All => (0, text),
+            Scope::Header => (0, &text[..header_end.unwrap_or(text.len())]),
+            Scope::Body => match header_end {
+                Some(it) => (it + 1, &text[it + 1..]),
+                None => (text.len(), ""),
+            },
+        }
+    }
+}
+
+/// Wraps the matcher so that it only looks at the part of the entry in `scope`.
+pub fn scoped(matcher: Box<dyn Matcher>, scope: Scope) -> Box<dyn Matcher> {
+    match scope {
+        Scope::All => matcher,
+        _ => Box::new(ScopedMatcher(scope, matcher)),
+    }
+}
+
+pub struct ScopedMatcher(pub Scope, pub Box<dyn Matcher>);
+
+impl Matcher for ScopedMatcher {
+    fn matches<'a>(&self, s: &'a str) -> bool {
+        self.1.matches(self.0.part(s).1)
+    }
+
+    fn matches_entry<'a>(&self, s: &'a str, fields: &dyn Fields) -> bool {
+        self.1.matches_entry(self.0.part(s).1, fields)
+    }
+
+    fn spans<'a>(&self, s: &'a str) -> Vec<Range<usize>> {
+        let (offset, part) = self.0.part(s);
+        self.1.spans(part).into_iter().map(|it| it.start + offset..it.end + offset).collect()
+    }
+}
+
+/// Applies the inner matcher to the named field instead of the whole entry.  Never matches
+/// when the entry doesn't have that field.
+pub struct FieldMatcher(pub String, pub Box<dyn Matcher>);
+
+impl Matcher for FieldMatcher {
+    fn matches<'a>(&self, _s: &'a str) -> bool {
+        false
+    }
+
+    fn matches_entry<'a>(&self, _s: &'a str, fields: &dyn Fields) -> bool {
+        fields.field(&self.0).is_some_and(|it| self.1.matches(it))
+    }
+}
+
+pub struct AllMatcher(pub Vec<Box<dyn Matcher>>);
+pub struct AnyMatcher(pub Vec<Box<dyn Matcher>>);
+pub struct NotMatcher(pub Box<dyn Matcher>);
+
+impl Matcher for AllMatcher {
+    fn matches<'a>(&self, s: &'a str) -> bool {
+        self.0.iter().all(|m| m.matches(s))
+    }
+
+    fn matches_entry<'a>(&self, s: &'a str, fields: &dyn Fields) -> bool {
+        self.0.iter().all(|m| m.matches_entry(s, fields))
+    }
+
+    fn spans<'a>(&self, s: &'a str) -> Vec<Range<usize>> {
+        self.0.iter().flat_map(|m| m.spans(s)).collect()
+    }
+}
+
+impl Matcher for AnyMatcher {
+    fn matches<'a>(&self, s: &'a str) -> bool {
+        self.0.iter().any(|m| m.matches(s))
+    }
+
+    fn matches_entry<'a>(&self, s: &'a str, fields: &dyn Fields) -> bool {
+        self.0.iter().any(|m| m.matches_entry(s, fields))
+    }
+
+    fn spans<'a>(&self, s: &'a str) -> Vec<Range<usize>> {
+        self.0.iter().flat_map(|m| m.spans(s)).collect()
+    }
+}
+
+impl Matcher for NotMatcher {
+    fn matches<'a>(&self, s: &'a str) -> bool {
+        !self.0.matches(s)
+    }
+
+    fn matches_entry<'a>(&self, s: &'a str, fields: &dyn Fields) -> bool {
+        !self.0.matches_entry(s, fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lazy_captures_only_capture_when_a_field_is_asked_for() {
+        let regex = regex::Regex::new(r"^(?P<timestamp>\d{2}:\d{2}) (?P<level>\w+)").unwrap();
+        let text = "12:00 ERROR timeout";
+        let fields = LazyCaptures::new(&regex, text);
+        assert!(VerbatimMatcher("timeout".to_string()).matches_entry(text, &fields));
+        assert!(fields.captures.get().is_none());
+        assert!(FieldMatcher("level".to_string(), Box::new(EqualsMatcher("ERROR".to_string()))).matches_entry(text, &fields));
+        assert_eq!(fields.field("timestamp"), Some("12:00"));
+        assert_eq!(fields.field("thread"), None);
+    }
+
+    fn split(pattern: &str) -> (bool, bool, &str) {
+        let (options, rest) = PatternOptions::default().split_prefix(pattern).unwrap();
+        (options.ignore_case, options.whole_word, rest)
+    }
+
+    #[test]
+    fn prefix_flags() {
+        assert_eq!(split("(?i)timeout"), (true, false, "timeout"));
+        assert_eq!(split("(?iw)error"), (true, true, "error"));
+        assert_eq!(split("(?i)/timeout \\d+ms/"), (true, false, "/timeout \\d+ms/"));
+        assert_eq!(split("i:timeout"), (true, false, "timeout"));
+        assert_eq!(split("wi:error"), (true, true, "error"));
+        assert_eq!(split("i:/timeout \\d+ms/"), (true, false, "/timeout \\d+ms/"));
+        let defaults = PatternOptions { ignore_case: true, whole_word: true, scope: Scope::All };
+        let (options, rest) = defaults.split_prefix("(?IW)Error").unwrap();
+        assert_eq!((options.ignore_case, options.whole_word, rest), (false, false, "Error"));
+    }
+
+    #[test]
+    fn only_flags_before_a_colon_are_a_prefix() {
+        assert_eq!(split("key:value"), (false, false, "key:value"));
+        assert_eq!(split("ix:y"), (false, false, "ix:y"));
+        assert_eq!(split("http://host:8080/"), (false, false, "http://host:8080/"));
+        assert_eq!(split("I:i:x"), (false, false, "i:x"));
+        assert_eq!(split("a:b"), (false, false, "a:b"));
+        assert_eq!(split("hi:x"), (false, false, "hi:x"));
+    }
+
+    #[test]
+    fn prefixes_without_a_pattern_are_errors() {
+        let split = |pattern| PatternOptions::default().split_prefix(pattern);
+        assert!(split("i:").is_err());
+        assert!(split("W:").is_err());
+        assert!(split("(?i)").is_err());
+        assert!(split(":").is_err());
+        assert_eq!(split("::").unwrap().1, ":");
+        assert_eq!(split("").unwrap().1, "");
+    }
+
+    #[test]
+    fn a_leading_colon_escapes_a_prefix() {
+        assert_eq!(split(":i:timeout"), (false, false, "i:timeout"));
+        assert_eq!(split(":(?i)timeout"), (false, false, "(?i)timeout"));
+        assert_eq!(split("::foo"), (false, false, ":foo"));
+    }
+
+    #[test]
+    fn other_groups_are_part_of_the_pattern() {
+        assert_eq!(split("(?)x"), (false, false, "(?)x"));
+        assert_eq!(split("(?s)a.b"), (false, false, "(?s)a.b"));
+        assert_eq!(split("(?i"), (false, false, "(?i"));
+        assert_eq!(split("(?:a|b)"), (false, false, "(?:a|b)"));
+    }
+
+    #[test]
+    fn pattern_file_lines_with_colons_are_matched_as_is() {
+        let path = std::env::temp_dir().join(format!("logtools-patterns-{}.txt", std::process::id()));
+        std::fs::write(&path, "key:b\n::foo\n:a:b\ni:/timeout \\d+ms/\n").unwrap();
+        let mut scoped_patterns = BTreeMap::new();
+        let result = read_pattern_file(&path, &mut scoped_patterns, PatternOptions::default());
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+        let patterns = &scoped_patterns[&Scope::All];
+        assert_eq!(patterns.verbatims, vec!["key:b", ":foo", "a:b"]);
+        assert_eq!(patterns.regexes, vec!["(?i)timeout \\d+ms"]);
+    }
+
+    #[test]
+    fn scope_prefixes() {
+        let scope = |pattern| PatternOptions::default().split_prefix(pattern).unwrap().0.scope;
+        assert_eq!(scope("(?h)ERROR"), Scope::Header);
+        assert_eq!(scope("(?bi)exception"), Scope::Body);
+        assert_eq!(scope("(?hba)x"), Scope::All);
+        assert_eq!(scope("h:ERROR"), Scope::All);
+        assert_eq!(split("ab:c"), (false, false, "ab:c"));
+        assert_eq!(split("hb:x"), (false, false, "hb:x"));
+        assert_eq!(split("bah:"), (false, false, "bah:"));
+        let defaults = PatternOptions { scope: Scope::Header, ..PatternOptions::default() };
+        assert_eq!(defaults.split_prefix("(?a)x").unwrap().0.scope, Scope::All);
+        assert_eq!(defaults.split_prefix("(?b)x").unwrap().0.scope, Scope::Body);
+        assert_eq!(defaults.split_prefix("b:x").unwrap().0.scope, Scope::Header);
+    }
+
+    #[test]
+    fn scoped_matchers_only_see_their_part() {
+        let text = "12:00 ERROR failed\njava.net.ConnectException: timeout";
+        let matcher = |scope| scoped(Box::new(VerbatimMatcher("timeout".to_string())), scope);
+        assert!(matcher(Scope::All).matches(text));
+        assert!(matcher(Scope::Body).matches(text));
+        assert!(!matcher(Scope::Header).matches(text));
+        assert_eq!(matcher(Scope::Body).spans(text), vec![46..53]);
+    }
 }
@@ -0,0 +1,240 @@
+//! Merging log entries of several sources in timestamp order, whatever kind of source they come from.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::iter::Peekable;
+
+use logentry::entry::Zdt;
+
+use crate::record::Record;
+
+/// Yields the earliest of the next entries of all sources, along with the index of its source.
+/// Entries without a timestamp stay right after the entry before them in their source, the ones at the start
+/// of a source go first.  Ties go to the source that comes first.
+pub struct MergeIterator<'a> {
+    sources: Vec<Peekable<Box<dyn Iterator<Item = Record> + 'a>>>,
+    /// The timestamps of the next entries of the sources but `current`.
+    heap: BinaryHeap<Reverse<(Option<Zdt>, usize)>>,
+    /// The source of the entry yielded last.
+    current: Option<usize>,
+}
+
+impl<'a> MergeIterator<'a> {
+    pub fn new(sources: Vec<Box<dyn Iterator<Item = Record> + 'a>>) -> Self {
+        let mut sources = sources.into_iter().map(|it| it.peekable()).collect::<Vec<_>>();
+        let heap = sources.iter_mut()
+            .enumerate()
+            .filter_map(|(index, source)| source.peek().map(|it| Reverse((it.zdt, index))))
+            .collect();
+        MergeIterator { sources, heap, current: None }
+    }
+}
+
+impl<'a> Iterator for MergeIterator<'a> {
+    type Item = (Record, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(index) = self.current.take() {
+            match self.sources[index].peek() {
+                None => {},
+                Some(it) if it.zdt.is_none() => {
+                    self.current = Some(index);
+                    return self.sources[index].next().map(|it| (it, index));
+                },
+                Some(it) => self.heap.push(Reverse((it.zdt, index))),
+            }
+        }
+        let Reverse((_, index)) = self.heap.pop()?;
+        self.current = Some(index);
+        self.sources[index].next().map(|it| (it, index))
+    }
+}
+
+/// Re-sorts the entries of a source that are at most `window` out of order, by holding each entry back until
+/// an entry `window` later than it has been read.  An entry that is older than one already yielded is too late:
+/// it's handed to `late` and yielded right away.  Entries without a timestamp stay right after the entry before them.
+pub struct ReorderIterator<'a> {
+    inner: Box<dyn Iterator<Item = Record> + 'a>,
+    window: chrono::Duration,
+    late: Box<dyn FnMut(&Record) + 'a>,
+    /// Keyed by timestamp, the one of the entry before for entries without, and then read order.
+    pending: BTreeMap<(Option<Zdt>, u64), Record>,
+    read: u64,
+    last_read: Option<Zdt>,
+    newest: Option<Zdt>,
+    last_yielded: Option<Zdt>,
+    exhausted: bool,
+}
+
+impl<'a> ReorderIterator<'a> {
+    pub fn new(inner: Box<dyn Iterator<Item = Record> + 'a>, window: chrono::Duration, late: Box<dyn FnMut(&Record) + 'a>) -> Self {
+        ReorderIterator {
+            inner,
+            window,
+            late,
+            pending: BTreeMap::new(),
+            read: 0,
+            last_read: None,
+            newest: None,
+            last_yielded: None,
+            exhausted: false,
+        }
+    }
+
+    fn pop_ready(&mut self) -> Option<Record> {
+        let key = *self.pending.keys().next()?;
+        let ready = self.exhausted || match (key.0, self.newest) {
+            // A window reaching back past the earliest time there is holds every entry back till the end.
+            (Some(zdt), Some(newest)) => newest.checked_sub_signed(self.window).is_some_and(|it| zdt <= it),
+            _ => true,
+        };
+        if !ready {
+            return None;
+        }
+        let record = self.pending.remove(&key)?;
+        self.last_yielded = self.last_yielded.max(record.zdt);
+        Some(record)
+    }
+}
+
+impl<'a> Iterator for ReorderIterator<'a> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        loop {
+            if let Some(it) = self.pop_ready() {
+                return Some(it);
+            }
+            if self.exhausted {
+                return None;
+            }
+            match self.inner.next() {
+                None => self.exhausted = true,
+                Some(record) => {
+                    let zdt = record.zdt.or(self.last_read);
+                    self.last_read = zdt;
+                    if let (Some(zdt), Some(last)) = (record.zdt, self.last_yielded) {
+                        if zdt < last {
+                            (self.late)(&record);
+                            return Some(record);
+                        }
+                    }
+                    self.newest = self.newest.max(zdt);
+                    self.pending.insert((zdt, self.read), record);
+                    self.read += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(entries: &[(&str, Option<u32>)]) -> Box<dyn Iterator<Item = Record>> {
+        let records = entries.iter()
+            .map(|(text, minute)| Record {
+                text: text.to_string(),
+                zdt: minute.map(|it| chrono::TimeZone::timestamp_opt(&chrono::Utc, 60 * it as i64, 0).unwrap()),
+                json: None,
+            })
+            .collect::<Vec<_>>();
+        Box::new(records.into_iter())
+    }
+
+    fn merge(sources: Vec<Box<dyn Iterator<Item = Record>>>) -> Vec<(String, usize)> {
+        MergeIterator::new(sources).map(|(record, index)| (record.text, index)).collect()
+    }
+
+    #[test]
+    fn merges_in_timestamp_order() {
+        let merged = merge(vec![
+            source(&[("a1", Some(1)), ("a4", Some(4)), ("a5", Some(5))]),
+            source(&[("b2", Some(2)), ("b3", Some(3)), ("b6", Some(6))]),
+            source(&[]),
+        ]);
+        assert_eq!(merged, vec![
+            ("a1".to_string(), 0), ("b2".to_string(), 1), ("b3".to_string(), 1),
+            ("a4".to_string(), 0), ("a5".to_string(), 0), ("b6".to_string(), 1),
+        ]);
+    }
+
+    #[test]
+    fn ties_go_to_the_first_source() {
+        let merged = merge(vec![
+            source(&[("a1", Some(1)), ("a2", Some(2))]),
+            source(&[("b1", Some(1)), ("b2", Some(2))]),
+        ]);
+        let texts = merged.into_iter().map(|it| it.0).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["a1", "b1", "a2", "b2"]);
+    }
+
+    #[test]
+    fn entries_without_timestamps_stay_with_the_entry_before() {
+        let merged = merge(vec![
+            source(&[("a1", Some(1)), ("a3", Some(3)), ("a3 continued", None)]),
+            source(&[("b0 header", None), ("b2", Some(2)), ("b2 continued", None), ("b3", Some(3))]),
+        ]);
+        let texts = merged.into_iter().map(|it| it.0).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["b0 header", "a1", "b2", "b2 continued", "a3", "a3 continued", "b3"]);
+    }
+
+    fn reorder(entries: &[(&str, Option<u32>)], window_minutes: i64) -> (Vec<String>, Vec<String>) {
+        let late = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let reported = late.clone();
+        let reordered = ReorderIterator::new(
+            source(entries),
+            chrono::Duration::minutes(window_minutes),
+            Box::new(move |it: &Record| reported.borrow_mut().push(it.text.clone())),
+        ).map(|it| it.text).collect();
+        (reordered, late.take())
+    }
+
+    #[test]
+    fn reorders_within_the_window() {
+        let (reordered, late) = reorder(&[("2", Some(2)), ("1", Some(1)), ("3", Some(3)), ("5", Some(5)), ("4", Some(4))], 1);
+        assert_eq!(reordered, vec!["1", "2", "3", "4", "5"]);
+        assert!(late.is_empty());
+    }
+
+    #[test]
+    fn entries_beyond_the_window_are_late() {
+        let (reordered, late) = reorder(&[("3", Some(3)), ("5", Some(5)), ("1", Some(1)), ("6", Some(6))], 1);
+        assert_eq!(reordered, vec!["3", "1", "5", "6"]);
+        assert_eq!(late, vec!["1"]);
+    }
+
+    #[test]
+    fn windows_beyond_the_earliest_time_hold_every_entry_back() {
+        let (reordered, late) = reorder(&[("3", Some(3)), ("1", Some(1)), ("2", Some(2))], 10_000_000_000_000);
+        assert_eq!(reordered, vec!["1", "2", "3"]);
+        assert!(late.is_empty());
+    }
+
+    #[test]
+    fn reordered_entries_without_timestamps_stay_with_the_entry_before() {
+        let (reordered, late) = reorder(&[("header", None), ("2", Some(2)), ("2 continued", None), ("1", Some(1)), ("3", Some(3))], 1);
+        assert_eq!(reordered, vec!["header", "1", "2", "2 continued", "3"]);
+        assert!(late.is_empty());
+    }
+
+    #[test]
+    fn sources_in_different_offsets_merge_in_utc_order() {
+        let iso8601 = crate::formats::find_format("iso8601").unwrap();
+        let entry_regex = regex::Regex::new(&iso8601.entry_pattern).unwrap();
+        let lines = |lines: &[&str]| Box::new(lines.iter().map(|it| it.to_string()).collect::<Vec<_>>().into_iter());
+        let berlin = lines(&["2020-09-01T14:00:00+02:00 berlin 12:00", "2020-09-01T15:30:00+02:00 berlin 13:30"]);
+        let new_york = lines(&["2020-09-01T07:00:00-05:00 new york 12:00", "2020-09-01T08:00:00-05:00 new york 13:00"]);
+        let merged = MergeIterator::new(vec![
+            Box::new(crate::record::log_entries(&entry_regex, Some(&iso8601.timestamp_pattern), berlin)),
+            Box::new(crate::record::log_entries(&entry_regex, Some(&iso8601.timestamp_pattern), new_york)),
+        ]).map(|(record, index)| (record.text, index)).collect::<Vec<_>>();
+        assert_eq!(merged, vec![
+            ("2020-09-01T14:00:00+02:00 berlin 12:00".to_string(), 0),
+            ("2020-09-01T07:00:00-05:00 new york 12:00".to_string(), 1),
+            ("2020-09-01T08:00:00-05:00 new york 13:00".to_string(), 1),
+            ("2020-09-01T15:30:00+02:00 berlin 13:30".to_string(), 0),
+        ]);
+    }
+}
@@ -0,0 +1,242 @@
+//! Structured output of log entries, so that they can go straight into jq, a spreadsheet or a database
+//! without parsing the text again.
+//!
+//! Every entry has its source, its timestamp in ISO 8601, its fields, and its full text.
+
+use std::cell::Cell;
+use std::io::Write;
+
+use logentry::entry::Zdt;
+
+pub const OUTPUT_FORMATS: &[&str] = &["text", "json", "ndjson", "csv", "logfmt"];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// The entries as they are, not handled here.
+    Text,
+    /// A single array of objects.
+    Json,
+    Ndjson,
+    /// With a header, the fields as columns.
+    Csv,
+    Logfmt,
+}
+
+impl OutputFormat {
+    /// Takes one of `OUTPUT_FORMATS`.
+    pub fn from_name(name: &str) -> Result<OutputFormat, String> {
+        match name {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "csv" => Ok(OutputFormat::Csv),
+            "logfmt" => Ok(OutputFormat::Logfmt),
+            _ => Err(format!("unsupported output format `{}`, expected one of {}", name, OUTPUT_FORMATS.join(", "))),
+        }
+    }
+}
+
+pub struct OutputEntry<'a> {
+    pub source: Option<&'a str>,
+    pub zdt: Option<&'a Zdt>,
+    /// Whether the timestamp was taken as UTC without knowing its timezone, so that it's written without an offset.
+    pub naive: bool,
+    pub fields: Vec<(String, String)>,
+    pub text: &'a str,
+}
+
+/// The fields of an entry: the named capture groups of the entry pattern but `timestamp`, which is output
+/// parsed, or the values of an NDJSON entry.
+pub fn entry_fields(entry_regex: &regex::Regex, text: &str, json: Option<&serde_json::Value>) -> Vec<(String, String)> {
+    if let Some(value) = json {
+        return crate::json::json_fields(value).map_or(Vec::new(), |it| it.0.into_iter().collect());
+    }
+    let captures = match entry_regex.captures(text) {
+        Some(it) => it,
+        None => return Vec::new(),
+    };
+    entry_regex.capture_names()
+        .flatten()
+        .filter(|it| *it != "timestamp")
+        .filter_map(|name| captures.name(name).map(|it| (name.to_string(), it.as_str().to_string())))
+        .collect()
+}
+
+/// The columns of CSV output for entries of this entry pattern, see `entry_fields`.
+pub fn field_columns(entry_regex: &regex::Regex) -> Vec<String> {
+    entry_regex.capture_names()
+        .flatten()
+        .filter(|it| *it != "timestamp")
+        .map(|it| it.to_string())
+        .collect()
+}
+
+/// Writes entries in any `OutputFormat` but `Text`.  `finish` has to be called after the last entry.
+pub struct StructuredWriter {
+    format: OutputFormat,
+    columns: Vec<String>,
+    started: Cell<bool>,
+}
+
+impl StructuredWriter {
+    /// `columns` are the fields that get a CSV column, the other formats write whatever fields an entry has.
+    pub fn new(format: OutputFormat, columns: Vec<String>) -> Self {
+        StructuredWriter { format, columns, started: Cell::new(false) }
+    }
+
+    pub fn write(&self, output: &mut dyn Write, entry: &OutputEntry) -> std::io::Result<()> {
+        let first = !self.started.replace(true);
+        let timestamp = entry.zdt.map(|it| match entry.naive {
+            true => it.naive_utc().format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+            false => it.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+        });
+        match self.format {
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                // Written by hand to keep the keys in this order.
+                let fields = entry.fields.iter()
+                    .map(|(name, value)| format!("{}:{}", serde_json::Value::from(name.as_str()), serde_json::Value::from(value.as_str())))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let object = format!(
+                    "{{\"source\":{},\"timestamp\":{},\"fields\":{{{}}},\"text\":{}}}",
+                    serde_json::json!(entry.source),
+                    serde_json::json!(timestamp),
+                    fields,
+                    serde_json::json!(entry.text),
+                );
+                if self.format == OutputFormat::Json {
+                    output.write_all(if first { b"[\n" } else { b",\n" })?;
+                }
+                output.write_all(object.as_bytes())?;
+                if self.format == OutputFormat::Ndjson {
+                    output.write_all(b"\n")?;
+                }
+            },
+            OutputFormat::Csv => {
+                if first {
+                    self.write_csv_header(output)?;
+                }
+                let mut values = vec![entry.source.unwrap_or(""), timestamp.as_deref().unwrap_or("")];
+                for column in &self.columns {
+                    values.push(entry.fields.iter().find(|it| it.0 == *column).map_or("", |it| it.1.as_str()));
+                }
+                values.push(entry.text);
+                write_csv_row(output, values)?;
+            },
+            OutputFormat::Logfmt => {
+                let mut pairs = Vec::new();
+                if let Some(it) = entry.source {
+                    pairs.push(("source", it));
+                }
+                if let Some(it) = timestamp.as_deref() {
+                    pairs.push(("timestamp", it));
+                }
+                pairs.extend(entry.fields.iter().map(|it| (it.0.as_str(), it.1.as_str())));
+                pairs.push(("text", entry.text));
+                let line = pairs.into_iter()
+                    .map(|(key, value)| format!("{}={}", key, logfmt_value(value)))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                output.write_all(line.as_bytes())?;
+                output.write_all(b"\n")?;
+            },
+            OutputFormat::Text => unreachable!("text output is written as is"),
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self, output: &mut dyn Write) -> std::io::Result<()> {
+        match (self.format, self.started.get()) {
+            (OutputFormat::Json, true) => output.write_all(b"\n]\n"),
+            (OutputFormat::Json, false) => output.write_all(b"[]\n"),
+            (OutputFormat::Csv, false) => self.write_csv_header(output),
+            _ => Ok(()),
+        }
+    }
+
+    fn write_csv_header(&self, output: &mut dyn Write) -> std::io::Result<()> {
+        let mut names = vec!["source", "timestamp"];
+        names.extend(self.columns.iter().map(|it| it.as_str()));
+        names.push("text");
+        write_csv_row(output, names)
+    }
+}
+
+fn write_csv_row(output: &mut dyn Write, values: Vec<&str>) -> std::io::Result<()> {
+    let row = values.into_iter()
+        .map(|it| match it.contains(&[',', '"', '\n', '\r'][..]) {
+            true => format!("\"{}\"", it.replace('"', "\"\"")),
+            false => it.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    output.write_all(row.as_bytes())?;
+    output.write_all(b"\r\n")
+}
+
+fn logfmt_value(value: &str) -> String {
+    match value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '=' || c == '"') {
+        true => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\r', "\\r").replace('\t', "\\t")),
+        false => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(format: OutputFormat, entries: &[OutputEntry]) -> String {
+        let writer = StructuredWriter::new(format, vec!["level".to_string()]);
+        let mut output = Vec::new();
+        for entry in entries {
+            writer.write(&mut output, entry).unwrap();
+        }
+        writer.finish(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn entry<'a>(zdt: &'a Zdt, naive: bool, text: &'a str) -> OutputEntry<'a> {
+        OutputEntry {
+            source: Some("app.log"),
+            zdt: Some(zdt),
+            naive,
+            fields: vec![("level".to_string(), "ERROR".to_string())],
+            text,
+        }
+    }
+
+    #[test]
+    fn csv_rows_are_escaped() {
+        let mut output = Vec::new();
+        write_csv_row(&mut output, vec!["plain", "a,b", "say \"hi\"", "two\nlines", "cr\r", ""]).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",\"cr\r\",\r\n");
+    }
+
+    #[test]
+    fn csv_has_a_header_even_without_entries() {
+        assert_eq!(written(OutputFormat::Csv, &[]), "source,timestamp,level,text\r\n");
+        let zdt = chrono::DateTime::parse_from_rfc3339("2020-09-01T12:00:00.250Z").unwrap().with_timezone(&chrono::Utc);
+        assert_eq!(
+            written(OutputFormat::Csv, &[entry(&zdt, false, "failed, retrying")]),
+            "source,timestamp,level,text\r\napp.log,2020-09-01T12:00:00.250Z,ERROR,\"failed, retrying\"\r\n",
+        );
+    }
+
+    #[test]
+    fn naive_timestamps_have_no_offset() {
+        let zdt = chrono::DateTime::parse_from_rfc3339("2020-09-01T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        assert_eq!(written(OutputFormat::Logfmt, &[entry(&zdt, true, "x")]), "source=app.log timestamp=2020-09-01T12:00:00 level=ERROR text=x\n");
+        assert_eq!(written(OutputFormat::Logfmt, &[entry(&zdt, false, "x")]), "source=app.log timestamp=2020-09-01T12:00:00Z level=ERROR text=x\n");
+    }
+
+    #[test]
+    fn json_is_one_array() {
+        assert_eq!(written(OutputFormat::Json, &[]), "[]\n");
+        let zdt = chrono::DateTime::parse_from_rfc3339("2020-09-01T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        assert_eq!(
+            written(OutputFormat::Json, &[entry(&zdt, false, "a"), entry(&zdt, false, "b")]),
+            "[\n{\"source\":\"app.log\",\"timestamp\":\"2020-09-01T12:00:00Z\",\"fields\":{\"level\":\"ERROR\"},\"text\":\"a\"},\n\
+             {\"source\":\"app.log\",\"timestamp\":\"2020-09-01T12:00:00Z\",\"fields\":{\"level\":\"ERROR\"},\"text\":\"b\"}\n]\n",
+        );
+    }
+}
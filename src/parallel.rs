@@ -0,0 +1,158 @@
+//! Searching a whole file, memory-mapped, on all cores.
+//!
+//! The file is split into chunks that begin at the first line of a log entry, so that every
+//! chunk can be taken apart into log entries independently.  Chunks are matched in batches, and
+//! the results of a batch are handed out in the original order.
+
+use std::ops::Range;
+
+use bstr::ByteSlice;
+use rayon::prelude::*;
+
+use crate::inputs::Encoding;
+
+pub const CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Memory-maps the file, unless it's empty or compressed, neither of which is worth searching this way.
+pub fn map_plain_file(path: &std::path::Path) -> std::io::Result<Option<memmap2::Mmap>> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("opening file {}: {}", path.to_string_lossy(), e)))?;
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    // Like with any other mmap-based search, truncating the file while it's being searched may crash the process.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    Ok(match crate::inputs::is_compressed(&map) {
+        true => None,
+        false => Some(map),
+    })
+}
+
+/// Whether the line, decoded from `encoding`, is the first line of a log entry.  Decoded first, the same as
+/// when reading lines, so that patterns with non-ASCII characters match the same in any encoding.
+fn is_entry_start(line: &[u8], entry_regex: &regex::Regex, encoding: Encoding) -> bool {
+    entry_regex.is_match(&encoding.decode_borrowed(line))
+}
+
+/// Splits `data` into chunks of about `chunk_size` bytes, each but the first one beginning at a line
+/// that matches `entry_regex`.
+pub fn split_at_entries(data: &[u8], entry_regex: &regex::Regex, encoding: Encoding, chunk_size: usize) -> Vec<Range<usize>> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let mut end = std::cmp::min(start + chunk_size, data.len());
+        // Move the boundary to the beginning of the next log entry.
+        while end < data.len() {
+            end = match data[end..].find_byte(b'\n') {
+                None => data.len(),
+                Some(it) => end + it + 1,
+            };
+            if end < data.len() && is_entry_start(line_at(data, end), entry_regex, encoding) {
+                break;
+            }
+        }
+        chunks.push(start..end);
+        start = end;
+    }
+    chunks
+}
+
+fn line_at(data: &[u8], start: usize) -> &[u8] {
+    let end = data[start..].find_byte(b'\n').map_or(data.len(), |it| start + it);
+    trim_cr(&data[start..end])
+}
+
+fn trim_cr(line: &[u8]) -> &[u8] {
+    match line.last() {
+        Some(b'\r') => &line[..line.len() - 1],
+        _ => line,
+    }
+}
+
+/// Byte ranges of the log entries in `data`, without the line break that ends each entry.
+/// Lines before the first entry beginning make up an entry of their own.
+pub fn entry_ranges(data: &[u8], entry_regex: &regex::Regex, encoding: Encoding) -> Vec<Range<usize>> {
+    let mut entries = Vec::new();
+    let mut entry_start = None;
+    let mut entry_end = 0;
+    let mut position = 0;
+    while position < data.len() {
+        let line_end = data[position..].find_byte(b'\n').map_or(data.len(), |it| position + it);
+        let line = trim_cr(&data[position..line_end]);
+        if entry_start.is_none() {
+            entry_start = Some(position);
+        } else if is_entry_start(line, entry_regex, encoding) {
+            entries.push(entry_start.unwrap()..entry_end);
+            entry_start = Some(position);
+        }
+        entry_end = position + line.len();
+        position = line_end + 1;
+    }
+    if let Some(start) = entry_start {
+        entries.push(start..entry_end);
+    }
+    entries
+}
+
+/// The text of a log entry, without the `\r` of CRLF line breaks, the same as when reading it line by line.
+fn entry_text(entry: &[u8], encoding: Encoding) -> String {
+    let text = encoding.decode(entry);
+    match text.contains("\r\n") {
+        true => text.replace("\r\n", "\n"),
+        false => text,
+    }
+}
+
+/// Calls `found` with the text of every log entry in `data` that is `selected`, in the order of the entries.
+/// The encoding must be ASCII-compatible.
+pub fn search<S, F, E>(data: &[u8], entry_regex: &regex::Regex, encoding: Encoding, selected: S, mut found: F) -> Result<(), E>
+where
+    S: Fn(&str) -> bool + Sync,
+    F: FnMut(&str) -> Result<(), E>,
+{
+    let chunks = split_at_entries(data, entry_regex, encoding, CHUNK_SIZE);
+    let batch_size = rayon::current_num_threads() * 4;
+    for batch in chunks.chunks(batch_size) {
+        let results = batch.par_iter()
+            .map(|chunk| {
+                let chunk_data = &data[chunk.clone()];
+                entry_ranges(chunk_data, entry_regex, encoding).into_iter()
+                    .map(|it| entry_text(&chunk_data[it], encoding))
+                    .filter(|it| selected(it))
+                    .collect::<Vec<String>>()
+            })
+            .collect::<Vec<Vec<String>>>();
+        for text in results.iter().flatten() {
+            found(text)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_found_in_decoded_lines() {
+        let data = b"D\xe9but 1\n  suite\nD\xe9but 2\n";
+        let entry_regex = regex::Regex::new("^Début").unwrap();
+        assert_eq!(entry_ranges(data, &entry_regex, Encoding::Latin1), vec![0..15, 16..23]);
+        assert_eq!(split_at_entries(data, &entry_regex, Encoding::Latin1, 1), vec![0..16, 16..24]);
+    }
+
+    #[test]
+    fn crlf_entries_are_the_same_as_read_line_by_line() {
+        let data = b"2020-09-01 first\r\n  at a\r\n  at b\r\n2020-09-02 second\r\n  at c\r\n";
+        let entry_regex = regex::Regex::new(r"^\d{4}-\d{2}-\d{2} ").unwrap();
+        let lines = crate::inputs::lines(Box::new(std::io::Cursor::new(data.to_vec())), Encoding::Utf8);
+        let sequential = crate::record::log_entries(&entry_regex, None, lines).map(|it| it.text).collect::<Vec<_>>();
+        let mut parallel = Vec::new();
+        search(data, &entry_regex, Encoding::Utf8, |_| true, |it| {
+            parallel.push(it.to_string());
+            Ok::<_, ()>(())
+        }).unwrap();
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel, vec!["2020-09-01 first\n  at a\n  at b", "2020-09-02 second\n  at c"]);
+    }
+}
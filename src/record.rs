@@ -0,0 +1,26 @@
+use logentry::entry::{LogEntryIterator, Zdt};
+
+use crate::timerange::parse_timestamp;
+
+/// A log entry that, unlike the ones coming out of `LogEntryIterator`, doesn't depend on who produced it,
+/// so that entries from different kinds of sources can go through the same pipeline.
+pub struct Record {
+    pub text: String,
+    pub zdt: Option<Zdt>,
+    /// The parsed line of an NDJSON source, `Null` when the line isn't JSON.  None for other sources.
+    pub json: Option<serde_json::Value>,
+}
+
+/// The log entries of `lines`.  Their timestamps are parsed by `timerange::parse_timestamp` rather than by
+/// `LogEntryIterator`, which drops the UTC offset of patterns like `%z`.
+pub fn log_entries<'a>(
+    entry_regex: &'a regex::Regex,
+    timestamp_pattern: Option<&'a str>,
+    lines: Box<dyn Iterator<Item = String>>,
+) -> impl Iterator<Item = Record> + 'a {
+    LogEntryIterator::new(entry_regex, None, lines).map(move |it| Record {
+        zdt: timestamp_pattern.and_then(|pattern| parse_timestamp(entry_regex, &it.text, pattern)),
+        text: it.text,
+        json: None,
+    })
+}
@@ -0,0 +1,226 @@
+use chrono::TimeZone;
+
+use logentry::entry::Zdt;
+
+pub const TIME_BOUND_HELP: &str = "Either an absolute timestamp in the --timestamp-pattern format or like `2020-09-01 14:02:00`, \
+    or relative to the current time, like `-15m` (units are ms, s, m, h, d).";
+
+/// Bounds parsed from `--since`/`--until`, both inclusive.
+pub struct TimeRange {
+    pub since: Option<Zdt>,
+    pub until: Option<Zdt>,
+}
+
+impl TimeRange {
+    /// `naive` tells whether the timestamps are local times taken as UTC, rather than converted to UTC.
+    pub fn from_cli(cli: &clap::ArgMatches, timestamp_pattern: &str, naive: bool) -> Result<TimeRange, String> {
+        let now = now(naive);
+        let parse = |name: &str| match cli.value_of(name) {
+            Some(it) => parse_time_bound(it, timestamp_pattern, now).map(Some),
+            None => Ok(None),
+        };
+        Ok(TimeRange {
+            since: parse("since")?,
+            until: parse("until")?,
+        })
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Entries without a timestamp are only contained in an unbounded range.
+    pub fn contains(&self, zdt: Option<&Zdt>) -> bool {
+        match zdt {
+            None => self.is_unbounded(),
+            Some(it) => self.since.is_none_or(|since| *it >= since) && self.until.is_none_or(|until| *it <= until),
+        }
+    }
+}
+
+/// The current time, as the local wall-clock time taken as UTC for naive timestamps, the same way they are.
+fn now(naive: bool) -> Zdt {
+    match naive {
+        true => chrono::Utc.from_utc_datetime(&chrono::Local::now().naive_local()),
+        false => chrono::Utc::now(),
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref DURATION_REGEX: regex::Regex = regex::Regex::new(r"^([+-]?)(\d+(?:\.\d+)?)(ms|s|m|h|d)$").unwrap();
+}
+
+/// Parses a signed duration like `500ms`, `-15m` or `+2.4s`.  Units are `ms`, `s`, `m`, `h`, `d`.
+pub fn parse_duration(text: &str) -> Result<chrono::Duration, String> {
+    let captured = DURATION_REGEX.captures(text.trim())
+        .ok_or_else(|| format!("`{}` is not a duration like `500ms`, `-15m` or `+2.4s`", text))?;
+    let amount = captured[2].parse::<f64>().map_err(|e| format!("invalid duration `{}`: {}", text, e))?;
+    let millis = amount * match &captured[3] {
+        "ms" => 1.0,
+        "s" => 1000.0,
+        "m" => 60.0 * 1000.0,
+        "h" => 60.0 * 60.0 * 1000.0,
+        _ => 24.0 * 60.0 * 60.0 * 1000.0,
+    };
+    let duration = chrono::Duration::microseconds((millis * 1000.0).round() as i64);
+    Ok(match &captured[1] {
+        "-" => -duration,
+        _ => duration,
+    })
+}
+
+const FALLBACK_PATTERNS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+/// Parses either a timestamp relative to `now`, e.g. `-15m` or `-2h` (see `parse_duration`),
+/// or an absolute one.  Absolute timestamps are tried with `timestamp_pattern` first, then as
+/// `2020-09-01 14:02[:00[.000]]`, with either a space or a `T` separator.
+pub fn parse_time_bound(text: &str, timestamp_pattern: &str, now: Zdt) -> Result<Zdt, String> {
+    let text = text.trim();
+    if text == "now" {
+        return Ok(now);
+    }
+    if text.starts_with('-') || text.starts_with('+') {
+        return now.checked_add_signed(parse_duration(text)?)
+            .ok_or_else(|| format!("`{}` is too far from the current time", text));
+    }
+    std::iter::once(timestamp_pattern)
+        .chain(FALLBACK_PATTERNS.iter().cloned())
+        .find_map(|pattern| parse_timestamp_text(text, pattern))
+        .ok_or_else(|| format!(
+            "can't parse `{}` as a timestamp: expected `{}`, `2020-09-01 14:02:00` or a relative time like `-15m`",
+            text, timestamp_pattern
+        ))
+}
+
+/// Parses a positive duration option like `--idle-timeout`, see `parse_duration`.  Zero is rejected too, as waiting
+/// for no time at all would just spin.
+pub fn parse_timeout(cli: &clap::ArgMatches, name: &str) -> Result<std::time::Duration, String> {
+    let text = cli.value_of(name).unwrap();
+    match parse_duration(text)?.to_std() {
+        Ok(it) if !it.is_zero() => Ok(it),
+        _ => Err(format!("--{} `{}` must be positive", name, text)),
+    }
+}
+
+/// Parses the `timestamp` capture group of the entry's first line, see `parse_timestamp_text`.
+pub fn parse_timestamp(entry_regex: &regex::Regex, text: &str, timestamp_pattern: &str) -> Option<Zdt> {
+    let captured = entry_regex.captures(text)?;
+    parse_timestamp_text(captured.name("timestamp")?.as_str(), timestamp_pattern)
+}
+
+/// Parses a timestamp in `timestamp_pattern`.  Timestamps with a UTC offset, i.e. of patterns with `%z` or `%+`,
+/// are converted to UTC, the others are taken as UTC as they are.  The offset may be left out, as in the timestamps
+/// of the `iso8601` format.
+pub fn parse_timestamp_text(text: &str, timestamp_pattern: &str) -> Option<Zdt> {
+    let parse_naive = |pattern: &str| chrono::NaiveDateTime::parse_from_str(text, pattern).ok()
+        .map(|it| chrono::Utc.from_utc_datetime(&it));
+    match has_offset(timestamp_pattern) {
+        true => chrono::DateTime::parse_from_str(text, timestamp_pattern).ok().map(|it| it.with_timezone(&chrono::Utc))
+            .or_else(|| parse_naive(&without_offset(timestamp_pattern))),
+        false => parse_naive(timestamp_pattern),
+    }
+}
+
+/// The pattern with its UTC offset left out.
+fn without_offset(timestamp_pattern: &str) -> String {
+    timestamp_pattern
+        .replace("%+", "%Y-%m-%dT%H:%M:%S%.f")
+        .replace("%:z", "")
+        .replace("%#z", "")
+        .replace("%z", "")
+}
+
+/// Whether timestamps of the pattern have a UTC offset.
+pub fn has_offset(timestamp_pattern: &str) -> bool {
+    let pattern = timestamp_pattern.replace("%%", "");
+    ["%z", "%:z", "%#z", "%+"].iter().any(|it| pattern.contains(it))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> Zdt {
+        chrono::Utc.from_utc_datetime(&chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f").unwrap())
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("500ms"), Ok(chrono::Duration::milliseconds(500)));
+        assert_eq!(parse_duration("-15m"), Ok(chrono::Duration::minutes(-15)));
+        assert_eq!(parse_duration("+2.4s"), Ok(chrono::Duration::milliseconds(2400)));
+        assert_eq!(parse_duration("1d"), Ok(chrono::Duration::days(1)));
+        assert_eq!(parse_duration(" 2h "), Ok(chrono::Duration::hours(2)));
+        assert!(parse_duration("15").is_err());
+        assert!(parse_duration("15 minutes").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    fn timestamps_with_offsets_are_converted_to_utc() {
+        let nginx = crate::formats::find_format("nginx").unwrap();
+        let entry_regex = regex::Regex::new(&nginx.entry_pattern).unwrap();
+        let line = r#"10.0.0.1 - - [01/Sep/2020:14:00:00 +0200] "GET / HTTP/1.1" 200 612"#;
+        assert_eq!(parse_timestamp(&entry_regex, line, &nginx.timestamp_pattern), Some(at("2020-09-01 12:00:00")));
+        assert_eq!(parse_timestamp_text("2020-09-01T14:00:00.5-05:00", "%+"), Some(at("2020-09-01 19:00:00.5")));
+        assert_eq!(parse_timestamp_text("2020-09-01T14:00:00Z", "%+"), Some(at("2020-09-01 14:00:00")));
+        assert_eq!(parse_timestamp_text("2020-09-01 14:00:00", "%Y-%m-%d %H:%M:%S"), Some(at("2020-09-01 14:00:00")));
+        assert!(has_offset("%d/%b/%Y:%H:%M:%S %z"));
+    }
+
+    #[test]
+    fn iso8601_offsets_are_optional() {
+        let iso8601 = crate::formats::find_format("iso8601").unwrap();
+        let entry_regex = regex::Regex::new(&iso8601.entry_pattern).unwrap();
+        let parse = |line| parse_timestamp(&entry_regex, line, &iso8601.timestamp_pattern);
+        assert_eq!(parse("2020-09-01T14:00:00.250+02:00 started"), Some(at("2020-09-01 12:00:00.25")));
+        assert_eq!(parse("2020-09-01T14:00:00-0500 started"), Some(at("2020-09-01 19:00:00")));
+        assert_eq!(parse("2020-09-01T14:00:00Z started"), Some(at("2020-09-01 14:00:00")));
+        assert_eq!(parse("2020-09-01T14:00:00.5 started"), Some(at("2020-09-01 14:00:00.5")));
+        assert_eq!(parse_timestamp_text("2020-09-01T14:00:00", "%+"), Some(at("2020-09-01 14:00:00")));
+        assert!(!has_offset("%Y-%m-%d %H:%M:%S%%z"));
+    }
+
+    #[test]
+    fn time_bounds() {
+        let now = at("2020-09-01 14:30:00");
+        assert_eq!(parse_time_bound("-15m", "%Y-%m-%d %H:%M:%S,%3f", now), Ok(at("2020-09-01 14:15:00")));
+        assert_eq!(parse_time_bound("now", "%Y-%m-%d %H:%M:%S,%3f", now), Ok(now));
+        assert_eq!(parse_time_bound("2020-09-01 14:02:00,250", "%Y-%m-%d %H:%M:%S,%3f", now), Ok(at("2020-09-01 14:02:00.250")));
+        assert_eq!(parse_time_bound("2020-09-01T14:02", "%Y-%m-%d %H:%M:%S,%3f", now), Ok(at("2020-09-01 14:02:00")));
+        assert!(parse_time_bound("yesterday", "%Y-%m-%d %H:%M:%S,%3f", now).is_err());
+        assert!(parse_time_bound("-999999999999d", "%Y-%m-%d %H:%M:%S,%3f", now).is_err());
+        assert!(parse_time_bound("+999999999999d", "%Y-%m-%d %H:%M:%S,%3f", now).is_err());
+    }
+
+    #[test]
+    fn ranges_are_inclusive_and_skip_entries_without_timestamps() {
+        let range = TimeRange { since: Some(at("2020-09-01 14:00:00")), until: Some(at("2020-09-01 15:00:00")) };
+        assert!(range.contains(Some(&at("2020-09-01 14:00:00"))));
+        assert!(range.contains(Some(&at("2020-09-01 15:00:00"))));
+        assert!(!range.contains(Some(&at("2020-09-01 15:00:00.001"))));
+        assert!(!range.contains(None));
+        assert!(TimeRange { since: None, until: None }.contains(None));
+    }
+
+    #[test]
+    fn timeouts() {
+        let timeout = |value: &str| {
+            let cli = clap::App::new("test")
+                .arg(clap::Arg::with_name("idle-timeout").long("idle-timeout").takes_value(true))
+                .get_matches_from(vec!["test".to_string(), format!("--idle-timeout={}", value)]);
+            parse_timeout(&cli, "idle-timeout")
+        };
+        assert_eq!(timeout("500ms"), Ok(std::time::Duration::from_millis(500)));
+        assert_eq!(timeout("2s"), Ok(std::time::Duration::from_secs(2)));
+        assert!(timeout("-1s").is_err());
+        assert!(timeout("0s").is_err());
+        assert!(timeout("0ms").is_err());
+        assert!(timeout("soon").is_err());
+    }
+}